@@ -1,6 +1,7 @@
 use crate::error::ProxyResult;
 use rcgen::KeyUsagePurpose::{CrlSign, KeyCertSign};
 use rcgen::{BasicConstraints, CertificateParams, DnType, DnValue, Ia5String, IsCa, PrintableString, SanType};
+use std::net::IpAddr;
 use std::str::FromStr;
 use time::OffsetDateTime;
 
@@ -40,8 +41,11 @@ pub fn gen_ca() -> ProxyResult<()> {
 //接下来我们实现可以为每个域名生成一个证书
 pub fn gen_cert_for_sni(sni: impl AsRef<str>, ca: &str, key: &str) -> ProxyResult<(String, String)> {
     let mut params = CertificateParams::default();
-    //为某个SNI实现证书签发
-    params.subject_alt_names.push(SanType::DnsName(Ia5String::from_str(sni.as_ref())?));
+    //为某个SNI实现证书签发，直接访问IP的客户端没有SNI，证书里写IP
+    match IpAddr::from_str(sni.as_ref()) {
+        Ok(ip) => params.subject_alt_names.push(SanType::IpAddress(ip)),
+        Err(_) => params.subject_alt_names.push(SanType::DnsName(Ia5String::from_str(sni.as_ref())?)),
+    }
     params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
     params.not_before = OffsetDateTime::from_unix_timestamp(current_time()?)?;
     //这里我们的根证书有效时长只有一年
@@ -0,0 +1,276 @@
+use std::time::{Duration, SystemTime};
+use base64::Engine;
+use log::error;
+use base64::engine::general_purpose::STANDARD;
+use serde_json::{json, Value};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use crate::data::http::cookie::{parse_cookie, SetCookie};
+use crate::data::http::param::{parse_query, query_of};
+use crate::data::http::parser::HttpParser;
+use crate::data::http::{split_authority, split_url, HttpData, HttpPacket};
+use crate::data::{StreamDirection, StreamInfo};
+use crate::error::ProxyResult;
+
+//导出和导入HAR 1.2格式，浏览器的开发者工具和很多抓包工具都支持
+
+pub fn export_har(packets: &[&HttpPacket]) -> ProxyResult<String> {
+    let entries = packets.iter().map(|packet| export_entry(packet)).collect::<ProxyResult<Vec<_>>>()?;
+    let har = json!({
+        "log": {
+            "version": "1.2",
+            "creator": { "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") },
+            "pages": [],
+            "entries": entries,
+        }
+    });
+    Ok(serde_json::to_string_pretty(&har)?)
+}
+
+fn export_entry(packet: &HttpPacket) -> ProxyResult<Value> {
+    let (req, res) = (packet.request(), packet.response());
+    let wait = res.time().duration_since(req.time()).unwrap_or_default().as_secs_f64() * 1000.0;
+    let url = packet.url();
+    let query = parse_query(query_of(&url)).into_iter().map(|(name, value)| json!({ "name": name, "value": value })).collect::<Vec<_>>();
+    let cookies = parse_cookie(&req.header().get_all("Cookie")).into_iter()
+        .map(|(name, value)| json!({ "name": name, "value": value })).collect::<Vec<_>>();
+    let mut request = json!({
+        "method": packet.method(),
+        "url": url,
+        "httpVersion": req.header().version().as_str(),
+        "cookies": cookies,
+        "headers": export_headers(req),
+        "queryString": query,
+        "headersSize": head_size(req),
+        "bodySize": req.raw().len() - head_size(req),
+    });
+    if !req.body().decoded().is_empty() {
+        let mime = req.header().get("Content-Type").unwrap_or("");
+        let text = String::from_utf8_lossy(req.body().decoded()).to_string();
+        let mut post = json!({ "mimeType": mime, "text": text });
+        if mime.starts_with("application/x-www-form-urlencoded") {
+            post["params"] = parse_query(&text).into_iter().map(|(name, value)| json!({ "name": name, "value": value })).collect();
+        }
+        request["postData"] = post;
+    }
+    let set_cookies = res.header().get_all("Set-Cookie").into_iter().map(|value| {
+        let cookie = SetCookie::parse(value);
+        let mut item = json!({ "name": cookie.name, "value": cookie.value, "httpOnly": cookie.http_only, "secure": cookie.secure });
+        for (key, value) in [("path", cookie.path), ("domain", cookie.domain), ("expires", cookie.expires)] {
+            if !value.is_empty() { item[key] = Value::String(value); }
+        }
+        item
+    }).collect::<Vec<_>>();
+    let body = res.body().decoded();
+    let mut content = json!({ "size": body.len(), "mimeType": packet.content_type() });
+    //不是文本的body用base64保存
+    match std::str::from_utf8(body) {
+        Ok(text) => content["text"] = Value::String(text.to_string()),
+        Err(_) => {
+            content["text"] = Value::String(STANDARD.encode(body));
+            content["encoding"] = Value::String("base64".to_string());
+        }
+    }
+    //被拦截或者没等到响应就断开的请求，和浏览器一样记成状态码0
+    let (status, status_text, version) = if res.raw().is_empty() {
+        (0, "", "")
+    } else {
+        (packet.status().code(), packet.status().reason(), res.header().version().as_str())
+    };
+    let response = json!({
+        "status": status,
+        "statusText": status_text,
+        "httpVersion": version,
+        "cookies": set_cookies,
+        "headers": export_headers(res),
+        "content": content,
+        "redirectURL": res.header().get("Location").unwrap_or(""),
+        "headersSize": head_size(res),
+        "bodySize": res.raw().len() - head_size(res),
+    });
+    Ok(json!({
+        "startedDateTime": OffsetDateTime::from(req.time()).format(&Rfc3339)?,
+        "time": wait,
+        "request": request,
+        "response": response,
+        "cache": {},
+        "timings": { "send": 0, "wait": wait, "receive": 0 },
+        "connection": packet.stream().stream_id(),
+    }))
+}
+
+fn export_headers(data: &HttpData) -> Vec<Value> {
+    data.header().keys().iter().map(|(name, value)| json!({ "name": name, "value": value })).collect()
+}
+
+//报文头的长度，包括最后的空行
+fn head_size(data: &HttpData) -> usize {
+    data.raw().windows(4).position(|w| w == b"\r\n\r\n").map(|pos| pos + 4).unwrap_or(data.raw().len())
+}
+
+//HAR里没有原始数据，按里面的信息重新拼出HTTP/1.1报文再解析，body已经是解压过的，所以去掉压缩和分块相关的字段
+//导入失败的请求跳过，同时返回跳过的数量
+pub fn import_har(text: &str) -> ProxyResult<(Vec<HttpPacket>, usize)> {
+    let har: Value = serde_json::from_str(text)?;
+    let entries = har["log"]["entries"].as_array().ok_or("不是HAR文件")?;
+    let mut packets = vec![];
+    let mut skipped = 0;
+    for (index, entry) in entries.iter().enumerate() {
+        match import_entry(index, entry) {
+            Ok(packet) => packets.push(packet),
+            Err(e) => {
+                error!("第{}个请求导入失败：{}", index + 1, e.to_string());
+                skipped += 1;
+            }
+        }
+    }
+    Ok((packets, skipped))
+}
+
+fn import_entry(index: usize, entry: &Value) -> ProxyResult<HttpPacket> {
+    let started = entry["startedDateTime"].as_str().ok_or("缺少startedDateTime")?;
+    let req_time: SystemTime = OffsetDateTime::parse(started, &Rfc3339)?.into();
+    let wait = entry["time"].as_f64().unwrap_or(0.0).max(0.0);
+    let res_time = req_time + Duration::from_micros((wait * 1000.0).round() as u64);
+
+    let request = &entry["request"];
+    let method = request["method"].as_str().ok_or("缺少method")?;
+    let url = request["url"].as_str().ok_or("缺少url")?;
+    let (scheme, authority, _) = split_url(url).ok_or(format!("URL格式错误：{}", url))?;
+    let (host, port) = split_authority(authority, if scheme == "https" { 443 } else { 80 })?;
+    let mut head = format!("{} {} {}\r\n", method, url, import_version(&request["httpVersion"]));
+    if !import_headers(&request["headers"]).iter().any(|(name, _)| name.eq_ignore_ascii_case("Host")) {
+        head.push_str(&format!("Host: {}\r\n", authority));
+    }
+    let body = request["postData"]["text"].as_str().unwrap_or("").as_bytes().to_vec();
+    let req_raw = build_raw(head, &request["headers"], &body, true);
+    let req = parse_data(StreamDirection::ClientToServer, &req_raw, req_time, None)?;
+
+    let response = &entry["response"];
+    let status = response["status"].as_u64().ok_or("缺少status")?;
+    let stream_id = entry["connection"].as_str().map(|id| id.to_string()).unwrap_or(format!("har-{}", index));
+    let stream = StreamInfo::new(stream_id, scheme, format!("{}:{}", host, port));
+    //浏览器把取消或者被拦下的请求记成状态码0，这种请求没有响应
+    if status == 0 { return Ok(HttpPacket::from_data(stream, req, HttpData::new())); }
+    let head = format!("{} {} {}\r\n", import_version(&response["httpVersion"]), status, response["statusText"].as_str().unwrap_or(""));
+    let content = &response["content"];
+    let text = content["text"].as_str().unwrap_or("");
+    let body = match content["encoding"].as_str() {
+        Some("base64") => STANDARD.decode(text)?,
+        _ => text.as_bytes().to_vec(),
+    };
+    //这些响应按协议没有body，就算HAR里有内容也不能写进报文，否则会被解析成下一个报文
+    let has_body = !(method.eq_ignore_ascii_case("HEAD") || status < 200 || status == 204 || status == 304);
+    let res_raw = build_raw(head, &response["headers"], &body, has_body);
+    let res = parse_data(StreamDirection::ServerToClient, &res_raw, res_time, Some(method))?;
+    Ok(HttpPacket::from_data(stream, req, res))
+}
+
+//HTTP/2和HTTP/3的报文也按HTTP/1.1拼
+fn import_version(version: &Value) -> &str {
+    match version.as_str() {
+        Some(version) if version.eq_ignore_ascii_case("HTTP/1.0") => "HTTP/1.0",
+        _ => "HTTP/1.1",
+    }
+}
+
+fn import_headers(headers: &Value) -> Vec<(String, String)> {
+    headers.as_array().map(|headers| headers.iter().filter_map(|header| {
+        Some((header["name"].as_str()?.to_string(), header["value"].as_str().unwrap_or("").to_string()))
+    }).collect()).unwrap_or_default()
+}
+
+fn build_raw(mut head: String, headers: &Value, body: &[u8], has_body: bool) -> Vec<u8> {
+    for (name, value) in import_headers(headers) {
+        //HTTP/2的伪头字段以冒号开头
+        let skip = ["Content-Length", "Transfer-Encoding", "Content-Encoding"].iter().any(|h| h.eq_ignore_ascii_case(&name));
+        if skip || name.starts_with(":") { continue; }
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    if !has_body {
+        head.push_str("\r\n");
+        return head.into_bytes();
+    }
+    head.push_str(&format!("Content-Length: {}\r\n\r\n", body.len()));
+    let mut raw = head.into_bytes();
+    raw.extend(body);
+    raw
+}
+
+fn parse_data(direction: StreamDirection, raw: &[u8], time: SystemTime, method: Option<&str>) -> ProxyResult<HttpData> {
+    let mut parser = HttpParser::new(direction);
+    if let Some(method) = method { parser.push_method(method); }
+    let mut datas = parser.push(raw, time)?;
+    datas.extend(parser.finish()?);
+    Ok(datas.into_iter().next().ok_or("报文解析失败")?)
+}
+
+
+#[cfg(test)]
+mod test_har {
+    use std::time::{Duration, SystemTime};
+    use crate::data::har::{export_har, import_har};
+    use crate::data::http::parser::HttpParser;
+    use crate::data::http::{HttpData, HttpPacket};
+    use crate::data::{StreamDirection, StreamInfo};
+
+    #[test]
+    fn test_round_trip() {
+        let time = SystemTime::UNIX_EPOCH + Duration::from_millis(1_700_000_000_250);
+        let req = b"POST /login?x=1 HTTP/1.1\r\nHost: a.com\r\nCookie: sid=1\r\nContent-Type: application/x-www-form-urlencoded\r\nContent-Length: 7\r\n\r\nu=a&p=b";
+        let res = b"HTTP/1.1 200 OK\r\nSet-Cookie: t=2; Path=/; HttpOnly\r\nContent-Type: image/png\r\nContent-Length: 4\r\n\r\n\x89PNG";
+        let request = HttpParser::new(StreamDirection::ClientToServer).push(req, time).unwrap().remove(0);
+        let mut parser = HttpParser::new(StreamDirection::ServerToClient);
+        parser.push_method("POST");
+        let response = parser.push(res, time + Duration::from_millis(120)).unwrap().remove(0);
+        let packet = HttpPacket::from_data(StreamInfo::new("s1", "https", "a.com:443"), request, response);
+
+        let har = export_har(&[&packet]).unwrap();
+        assert!(har.contains("\"encoding\": \"base64\""));
+        let (packets, skipped) = import_har(&har).unwrap();
+        assert_eq!(skipped, 0);
+        let imported = &packets[0];
+        assert_eq!(imported.url(), "https://a.com/login?x=1");
+        assert_eq!(imported.request().time(), time);
+        assert_eq!(imported.response().time(), time + Duration::from_millis(120));
+        assert_eq!(imported.request().header().get("Cookie"), Some("sid=1"));
+        assert_eq!(imported.request().body().decoded(), b"u=a&p=b");
+        assert_eq!(imported.response().header().get("Set-Cookie"), Some("t=2; Path=/; HttpOnly"));
+        assert_eq!(imported.response().body().decoded(), b"\x89PNG");
+        assert_eq!(imported.stream().stream_id(), "s1");
+    }
+
+    #[test]
+    fn test_import_entries() {
+        let entry = |method: &str, status: u16, text: &str| format!(
+            "{{\"startedDateTime\": \"2024-01-01T00:00:00Z\", \"time\": 5, \"request\": {{\"method\": \"{}\", \"url\": \"https://a.com/\", \"headers\": []}}, \
+            \"response\": {{\"status\": {}, \"headers\": [{{\"name\": \"Content-Length\", \"value\": \"9\"}}], \"content\": {{\"text\": \"{}\"}}}}}}",
+            method, status, text);
+        let entries = [entry("GET", 0, ""), entry("GET", 304, "cached"), entry("GET", 204, "x"), entry("GET", 101, "x"),
+            entry("HEAD", 200, "hello"), entry("GET", 200, "hello"), "{\"request\": {}}".to_string()];
+        let har = format!("{{\"log\": {{\"entries\": [{}]}}}}", entries.join(","));
+        let (packets, skipped) = import_har(&har).unwrap();
+        assert_eq!(skipped, 1);
+        assert_eq!(packets.len(), 6);
+        //状态码0的请求只有请求没有响应
+        assert!(packets[0].response().raw().is_empty());
+        assert_eq!(packets[1].status().code(), 304);
+        assert_eq!(packets[1].response().raw(), b"HTTP/1.1 304 \r\n\r\n");
+        assert_eq!(packets[2].status().code(), 204);
+        assert_eq!(packets[3].status().code(), 101);
+        assert!(packets[4].response().body().decoded().is_empty());
+        assert_eq!(packets[5].response().body().decoded(), b"hello");
+    }
+
+    #[test]
+    fn test_no_response() {
+        let req = b"GET / HTTP/1.1\r\nHost: ads.com\r\n\r\n";
+        let request = HttpParser::new(StreamDirection::ClientToServer).push(req, SystemTime::now()).unwrap().remove(0);
+        let packet = HttpPacket::from_data(StreamInfo::new("s1", "http", "ads.com:80"), request, HttpData::new());
+        let har = export_har(&[&packet]).unwrap();
+        assert!(har.contains("\"status\": 0"));
+        let (packets, skipped) = import_har(&har).unwrap();
+        assert_eq!(skipped, 0);
+        assert!(packets[0].response().raw().is_empty());
+    }
+}
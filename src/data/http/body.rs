@@ -1,13 +1,112 @@
+use std::io::Read;
+use crate::error::ProxyResult;
+
+//解压后的body最大长度，防止压缩炸弹把内存撑爆
+const MAX_DECODED_LEN: u64 = 64 * 1024 * 1024;
+
 pub struct HttpBody {
-    //暂时不实现
+    //网络上传输的body，已经去掉了chunked编码，但还是压缩过的
     raw: Vec<u8>,
+    //按Content-Encoding解压之后的body，没有压缩时为空
+    decoded: Option<Vec<u8>>,
+    //解压失败的原因
+    decode_error: Option<String>,
 }
 
 impl HttpBody {
     pub fn new() -> HttpBody {
-        HttpBody {raw: vec![]}
+        HttpBody { raw: vec![], decoded: None, decode_error: None }
     }
     pub fn from_bytes(bs: Vec<u8>) -> HttpBody {
-        HttpBody { raw: bs }
+        HttpBody { raw: bs, decoded: None, decode_error: None }
+    }
+
+    //content_encoding是Content-Encoding字段的值，多个编码用逗号隔开，按顺序压缩的，所以要倒着解压
+    pub fn from_encoded(bs: Vec<u8>, content_encoding: &str) -> HttpBody {
+        let encodings = content_encoding.split(",").map(|e| e.trim().to_lowercase())
+            .filter(|e| !e.is_empty() && e != "identity").collect::<Vec<_>>();
+        if encodings.is_empty() || bs.is_empty() { return HttpBody::from_bytes(bs); }
+        let mut decoded = bs.clone();
+        for encoding in encodings.iter().rev() {
+            match decode(&decoded, encoding) {
+                Ok(res) => decoded = res,
+                Err(e) => {
+                    return HttpBody {
+                        raw: bs,
+                        decoded: None,
+                        decode_error: Some(format!("{}解压失败：{}", encoding, e.to_string())),
+                    };
+                }
+            }
+        }
+        HttpBody { raw: bs, decoded: Some(decoded), decode_error: None }
     }
-}
\ No newline at end of file
+
+    pub fn raw(&self) -> &[u8] {
+        &self.raw
+    }
+
+    //解压之后的body，没有压缩或者解压失败时就是原始数据
+    pub fn decoded(&self) -> &[u8] {
+        self.decoded.as_deref().unwrap_or(&self.raw)
+    }
+
+    pub fn is_encoded(&self) -> bool {
+        self.decoded.is_some()
+    }
+
+    pub fn decode_error(&self) -> Option<&str> {
+        self.decode_error.as_deref()
+    }
+}
+
+fn decode(bs: &[u8], encoding: &str) -> ProxyResult<Vec<u8>> {
+    match encoding {
+        "gzip" | "x-gzip" => read_all(flate2::read::MultiGzDecoder::new(bs)),
+        //标准的deflate是带zlib头的，但是有些服务器直接发送裸的deflate数据
+        "deflate" => read_all(flate2::read::ZlibDecoder::new(bs))
+            .or_else(|_| read_all(flate2::read::DeflateDecoder::new(bs))),
+        "br" => read_all(brotli::Decompressor::new(bs, 4096)),
+        "zstd" => read_all(zstd::stream::read::Decoder::new(bs)?),
+        _ => Err(format!("不支持的压缩格式：{}", encoding).into()),
+    }
+}
+
+fn read_all(reader: impl Read) -> ProxyResult<Vec<u8>> {
+    let mut res = vec![];
+    reader.take(MAX_DECODED_LEN + 1).read_to_end(&mut res)?;
+    if res.len() as u64 > MAX_DECODED_LEN { return Err("解压后的数据过大".into()); }
+    Ok(res)
+}
+
+
+#[cfg(test)]
+mod test_body {
+    use std::io::Write;
+    use crate::data::http::body::HttpBody;
+
+    #[test]
+    fn test_stacked_encodings() {
+        let text = b"{\"hello\":\"world\"}".repeat(100);
+        //先gzip再br
+        let mut gzip = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        gzip.write_all(&text).unwrap();
+        let gzip = gzip.finish().unwrap();
+        let mut br = vec![];
+        {
+            let mut writer = brotli::CompressorWriter::new(&mut br, 4096, 5, 22);
+            writer.write_all(&gzip).unwrap();
+        }
+        let body = HttpBody::from_encoded(br.clone(), "gzip, br");
+        assert!(body.is_encoded());
+        assert_eq!(body.decoded(), text.as_slice());
+        assert_eq!(body.raw(), br.as_slice());
+
+        let zstd = zstd::encode_all(text.as_slice(), 3).unwrap();
+        assert_eq!(HttpBody::from_encoded(zstd, "zstd").decoded(), text.as_slice());
+
+        let body = HttpBody::from_encoded(b"not gzip".to_vec(), "gzip");
+        assert!(body.decode_error().is_some());
+        assert_eq!(body.decoded(), b"not gzip");
+    }
+}
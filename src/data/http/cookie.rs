@@ -0,0 +1,73 @@
+//解析Cookie和Set-Cookie字段
+
+//请求的Cookie字段是a=1; b=2的形式，同一个请求里Cookie字段也可能出现多次
+pub fn parse_cookie(values: &[&str]) -> Vec<(String, String)> {
+    values.iter().flat_map(|value| value.split(";")).filter_map(|item| {
+        let item = item.trim();
+        if item.is_empty() { return None; }
+        let (name, value) = item.split_once("=").unwrap_or(("", item));
+        Some((name.trim().to_string(), value.trim().to_string()))
+    }).collect()
+}
+
+//响应里的一个Set-Cookie，没有出现的属性为空
+#[derive(Default)]
+pub struct SetCookie {
+    pub name: String,
+    pub value: String,
+    pub domain: String,
+    pub path: String,
+    pub expires: String,
+    pub max_age: String,
+    pub secure: bool,
+    pub http_only: bool,
+    pub same_site: String,
+}
+
+impl SetCookie {
+    //属性之间用;分隔，Expires的日期里有逗号，所以不能按逗号拆分
+    pub fn parse(value: &str) -> SetCookie {
+        let mut items = value.split(";");
+        let mut cookie = SetCookie::default();
+        let pair = items.next().unwrap_or("");
+        let (name, value) = pair.split_once("=").unwrap_or(("", pair));
+        cookie.name = name.trim().to_string();
+        cookie.value = value.trim().to_string();
+        for item in items {
+            let (key, value) = item.split_once("=").unwrap_or((item, ""));
+            let value = value.trim().to_string();
+            match key.trim().to_lowercase().as_str() {
+                "domain" => cookie.domain = value,
+                "path" => cookie.path = value,
+                "expires" => cookie.expires = value,
+                "max-age" => cookie.max_age = value,
+                "secure" => cookie.secure = true,
+                "httponly" => cookie.http_only = true,
+                "samesite" => cookie.same_site = value,
+                _ => {}
+            }
+        }
+        cookie
+    }
+}
+
+
+#[cfg(test)]
+mod test_cookie {
+    use crate::data::http::cookie::{parse_cookie, SetCookie};
+
+    #[test]
+    fn test_parse_cookie() {
+        let cookies = parse_cookie(&["a=1; b=x=y", "flag"]);
+        let cookies = cookies.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect::<Vec<_>>();
+        assert_eq!(cookies, [("a", "1"), ("b", "x=y"), ("", "flag")]);
+
+        let cookie = SetCookie::parse("sid=abc; Expires=Wed, 21 Oct 2026 07:28:00 GMT; Max-Age=3600; Domain=.a.com; Path=/; Secure; HttpOnly; SameSite=Lax");
+        assert_eq!((cookie.name.as_str(), cookie.value.as_str()), ("sid", "abc"));
+        assert_eq!(cookie.expires, "Wed, 21 Oct 2026 07:28:00 GMT");
+        assert_eq!(cookie.max_age, "3600");
+        assert_eq!((cookie.domain.as_str(), cookie.path.as_str()), (".a.com", "/"));
+        assert!(cookie.secure && cookie.http_only);
+        assert_eq!(cookie.same_site, "Lax");
+    }
+}
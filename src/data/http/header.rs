@@ -1,13 +1,51 @@
-use std::collections::HashMap;
+use log::trace;
 use crate::data::http::{HttpStatus, HttpVersion};
 use crate::error::ProxyResult;
 
+//请求头字段的集合，保留原始的顺序和大小写，同名的字段（比如多个Set-Cookie）可以出现多次，查找时不区分大小写
+#[derive(Clone, Default)]
+pub struct HttpHeaders {
+    fields: Vec<(String, String)>,
+}
+
+impl HttpHeaders {
+    pub fn new() -> HttpHeaders {
+        HttpHeaders { fields: vec![] }
+    }
+
+    //在最后追加一个字段，已有的同名字段不受影响
+    pub fn push(&mut self, key: impl ToString, value: impl ToString) {
+        self.fields.push((key.to_string(), value.to_string()));
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.fields.iter().find(|(k, _)| k.eq_ignore_ascii_case(key)).map(|(_, v)| v.as_str())
+    }
+
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        self.fields.iter().filter(|(k, _)| k.eq_ignore_ascii_case(key)).map(|(_, v)| v.as_str()).collect()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=(&str, &str)> {
+        self.fields.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+}
+
+//请求头按规范只能是ASCII，实际上有服务器会发送UTF-8或者其他编码的字段值，
+//按行解码，不是UTF-8的行按Latin-1逐字节转换，这样不会因为一个字段解析失败丢掉整个报文
+fn decode_head(raw: &[u8]) -> String {
+    raw.split(|b| *b == b'\n').map(|line| match std::str::from_utf8(line) {
+        Ok(line) => line.to_string(),
+        Err(_) => line.iter().map(|b| *b as char).collect(),
+    }).collect::<Vec<_>>().join("\n")
+}
+
 pub struct HttpHeader {
     method: String,
     uri: String,
     version: HttpVersion,
     status: HttpStatus,
-    keys: HashMap<String, String>,
+    keys: HttpHeaders,
 }
 
 impl HttpHeader {
@@ -17,13 +55,13 @@ impl HttpHeader {
             uri: "".to_string(),
             version: HttpVersion::Http10,
             status: HttpStatus::OK,
-            keys: Default::default(),
+            keys: HttpHeaders::new(),
         }
     }
     pub fn from_client(raw: Vec<u8>) -> ProxyResult<HttpHeader> {
         let mut http_header = HttpHeader::new();
-        let header_string = String::from_utf8(raw.to_vec())?.replace("\r\n", "\n");
-        println!("{}",header_string);
+        let header_string = decode_head(&raw).replace("\r\n", "\n");
+        trace!("{}",header_string);
         let line = header_string.lines().next().ok_or("传入的数据错误")?;
         let mut items = line.split(" ");
         //这里解析请求头的第一行
@@ -37,12 +75,14 @@ impl HttpHeader {
 
     pub fn from_server(raw: Vec<u8>) -> ProxyResult<HttpHeader> {
         let mut http_header = HttpHeader::new();
-        let header_string = String::from_utf8(raw.to_vec())?.replace("\r\n", "\n");
-        println!("{}",header_string);
+        let header_string = decode_head(&raw).replace("\r\n", "\n");
+        trace!("{}",header_string);
         let line = header_string.lines().next().ok_or("传入的数据错误")?;
         let mut items = line.split(" ");
         http_header.version = HttpVersion::from_stream_raw(items.next().ok_or("获取version失败")?)?;
-        http_header.status = HttpStatus::from_stream_raw(items.next().ok_or("获取code失败")?)?;
+        let code = items.next().ok_or("获取code失败")?;
+        //原因短语里可能有空格，剩下的部分都是原因短语
+        http_header.status = HttpStatus::from_stream_raw(code, &items.collect::<Vec<_>>().join(" "))?;
         http_header.handle_key_value(header_string)?;
         Ok(http_header)
         //到这里，服务器的响应头就解析完成了
@@ -50,16 +90,73 @@ impl HttpHeader {
 
     fn handle_key_value(&mut self, hdr_str: String) -> ProxyResult<()> {
         for (i, line) in hdr_str.split("\n").enumerate() {
-            if i == 0 { continue; }
-            let mut items = line.split(": ");
-            let key = items.next().ok_or("解析请求头字段失败")?;
-            let value = items.collect::<Vec<_>>().join(": "); //这里呢是防止有些字段是空的和中间有": "。
-            self.keys.insert(key.to_string(), value.to_string());
+            if i == 0 || line.is_empty() { continue; }
+            //只按第一个冒号分开，值里面可能还有冒号，冒号后面的空格可有可无
+            let (key, value) = line.split_once(":").ok_or(format!("解析请求头字段失败：{}", line))?;
+            self.keys.push(key.trim(), value.trim());
         }
         Ok(())
     }
 
-    pub fn keys(&self)->&HashMap<String, String>{
+    pub fn keys(&self) -> &HttpHeaders {
         &self.keys
     }
-}
\ No newline at end of file
+
+    pub fn keys_mut(&mut self) -> &mut HttpHeaders {
+        &mut self.keys
+    }
+
+    //请求头的字段名不区分大小写
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.keys.get(key)
+    }
+
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        self.keys.get_all(key)
+    }
+
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+
+    pub fn uri(&self) -> &str {
+        &self.uri
+    }
+
+    pub fn version(&self) -> &HttpVersion {
+        &self.version
+    }
+
+    pub fn status(&self) -> &HttpStatus {
+        &self.status
+    }
+}
+
+
+#[cfg(test)]
+mod test_header {
+    use crate::data::http::header::HttpHeader;
+
+    #[test]
+    fn test_repeated_fields() {
+        let raw = b"HTTP/1.1 200 OK\r\nSet-Cookie: a=1\r\ncontent-type: text/html\r\nSet-Cookie: b=2; Path=/\r\nX-Time:12:00".to_vec();
+        let header = HttpHeader::from_server(raw).unwrap();
+        assert_eq!(header.get_all("set-cookie"), vec!["a=1", "b=2; Path=/"]);
+        assert_eq!(header.get("Content-Type"), Some("text/html"));
+        assert_eq!(header.get("x-time"), Some("12:00"));
+        //原始的顺序和大小写都要保留
+        let keys = header.keys().iter().map(|(k, _)| k).collect::<Vec<_>>();
+        assert_eq!(keys, vec!["Set-Cookie", "content-type", "Set-Cookie", "X-Time"]);
+    }
+
+    #[test]
+    fn test_non_utf8_field() {
+        let mut raw = b"GET / HTTP/1.1\r\nHost: a.com\r\nX-Name: ".to_vec();
+        raw.extend([0xc4, 0xe3, 0xba, 0xc3]);
+        raw.extend("\r\nX-Utf8: 你好".as_bytes());
+        let header = HttpHeader::from_client(raw).unwrap();
+        assert_eq!(header.get("Host"), Some("a.com"));
+        assert_eq!(header.get("X-Name"), Some("\u{c4}\u{e3}\u{ba}\u{c3}"));
+        assert_eq!(header.get("X-Utf8"), Some("你好"));
+    }
+}
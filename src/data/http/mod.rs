@@ -1,15 +1,46 @@
+use std::time::SystemTime;
+use log::error;
 use crate::data::http::body::HttpBody;
 use crate::data::http::header::HttpHeader;
-use crate::data::StreamDirection;
+use crate::data::{FilterMode, StreamDirection, StreamInfo};
 use crate::error::ProxyResult;
 
 mod header;
 mod body;
+mod status;
+pub mod param;
+pub mod cookie;
+pub mod parser;
 
-const HTTP_HEAD_BODY_GAP: &'static [u8] = b"\r\n\r\n";
+pub use status::HttpStatus;
 
 //现在我们来解析一下HTTP数据
 
+//把完整的URL拆成协议、主机和路径三部分，路径为空时补上/
+pub fn split_url(url: &str) -> Option<(&str, &str, String)> {
+    let (scheme, rest) = url.split_once("://")?;
+    let pos = rest.find(['/', '?']).unwrap_or(rest.len());
+    let (authority, path) = rest.split_at(pos);
+    let path = if path.starts_with("/") { path.to_string() } else { format!("/{}", path) };
+    Some((scheme, authority, path))
+}
+
+//从host:port中取出主机和端口，没有端口时使用默认端口，IPv6地址带有[]
+pub fn split_authority(authority: &str, default_port: u16) -> ProxyResult<(String, u16)> {
+    if let Some(rest) = authority.strip_prefix("[") {
+        let (host, port) = rest.split_once("]").ok_or("获取HTTP地址失败")?;
+        let port = match port.strip_prefix(":") {
+            Some(port) => port.parse::<u16>()?,
+            None => default_port,
+        };
+        return Ok((format!("[{}]", host), port));
+    }
+    match authority.rsplit_once(":") {
+        Some((host, port)) => Ok((host.to_string(), port.parse::<u16>()?)),
+        None => Ok((authority.to_string(), default_port)),
+    }
+}
+
 pub enum HttpVersion {
     Http10,
     Http11,
@@ -27,22 +58,13 @@ impl HttpVersion {
             &_ => Err("请求版本解析失败".into())
         }
     }
-}
-
-pub enum HttpStatus {
-    OK = 200,
-    PartialContent = 206,
-    NotModified = 304,
-
-}
 
-impl HttpStatus {
-    pub fn from_stream_raw(code: &str) -> ProxyResult<HttpStatus> {
-        let code = code.parse::<i32>()?;
-        match code {
-            200 => Ok(HttpStatus::OK),
-            304 => Ok(HttpStatus::NotModified),
-            _ => Err("未知的Http状态码".into())
+    pub fn as_str(&self) -> &str {
+        match self {
+            HttpVersion::Http10 => "HTTP/1.0",
+            HttpVersion::Http11 => "HTTP/1.1",
+            HttpVersion::Http20 => "HTTP/2.0",
+            HttpVersion::Http30 => "HTTP/3.0",
         }
     }
 }
@@ -50,6 +72,12 @@ impl HttpStatus {
 pub struct HttpData {
     header: HttpHeader,
     body: HttpBody,
+    //收到第一个字节的时间
+    time: SystemTime,
+    //在网络上传输的总字节数
+    size: usize,
+    //网络上传输的原始数据
+    raw: Vec<u8>,
 }
 
 impl HttpData {
@@ -57,20 +85,35 @@ impl HttpData {
         HttpData {
             header: HttpHeader::new(),
             body: HttpBody::new(),
+            time: SystemTime::UNIX_EPOCH,
+            size: 0,
+            raw: vec![],
         }
     }
-    pub fn from_bytes(mut bs: Vec<u8>, direction: StreamDirection) -> ProxyResult<HttpData> {
-        let pos = bs.windows(HTTP_HEAD_BODY_GAP.len()).position(|w| w == HTTP_HEAD_BODY_GAP).ok_or("HTTP数据错误")?;
-        let hbs = bs.drain(..pos).collect::<Vec<_>>();
-        let hdr = match direction {
-            StreamDirection::ClientToServer => HttpHeader::from_client(hbs)?,
-            StreamDirection::ServerToClient => HttpHeader::from_server(hbs)?,
+    //由解析器切分好的报文组装，head不包含最后的空行，body已经去掉了chunked编码，raw是网络上的原始数据
+    //报文头解析失败时报文头是空的，只保留原始数据占位，否则后面的请求和响应就配错对了
+    pub fn from_parts(direction: StreamDirection, head: Vec<u8>, body: Vec<u8>, raw: Vec<u8>,
+                      trailers: Vec<(String, String)>, time: SystemTime) -> HttpData {
+        let parsed = match direction {
+            StreamDirection::ClientToServer => HttpHeader::from_client(head),
+            StreamDirection::ServerToClient => HttpHeader::from_server(head),
         };
-        let body = HttpBody::from_bytes(bs.drain(HTTP_HEAD_BODY_GAP.len()..).collect());
-        Ok(HttpData {
+        let mut hdr = parsed.unwrap_or_else(|e| {
+            error!("{}{}", direction, e.to_string());
+            HttpHeader::new()
+        });
+        for (key, value) in trailers {
+            hdr.keys_mut().push(key, value);
+        }
+        //Content-Encoding可能出现多次，合在一起按顺序解压
+        let body = HttpBody::from_encoded(body, &hdr.get_all("Content-Encoding").join(","));
+        HttpData {
             header: hdr,
             body,
-        })
+            time,
+            size: raw.len(),
+            raw,
+        }
     }
 
     pub fn header(&self) -> &HttpHeader {
@@ -80,6 +123,18 @@ impl HttpData {
     pub fn body(&self) -> &HttpBody {
         &self.body
     }
+
+    pub fn time(&self) -> SystemTime {
+        self.time
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn raw(&self) -> &[u8] {
+        &self.raw
+    }
 }
 
 
@@ -102,17 +157,75 @@ impl HttpMethod {
 }
 
 pub struct HttpPacket {
+    stream: StreamInfo,
     request: HttpData,
     response: HttpData,
+    //资源类型，列表按这个过滤
+    kind: FilterMode,
 }
 
 impl HttpPacket {
     pub fn new() -> HttpPacket {
         HttpPacket {
+            stream: StreamInfo::new("", "http", ""),
             request: HttpData::new(),
             response: HttpData::new(),
+            kind: FilterMode::None,
+        }
+    }
+
+    pub fn from_data(stream: StreamInfo, request: HttpData, response: HttpData) -> HttpPacket {
+        let mut packet = HttpPacket { stream, request, response, kind: FilterMode::None };
+        packet.kind = FilterMode::classify(&packet);
+        packet
+    }
+
+    pub fn stream(&self) -> &StreamInfo {
+        &self.stream
+    }
+
+    //代理请求里的URI可能是完整地址，也可能只有路径，只有路径时用Host拼出完整地址
+    pub fn url(&self) -> String {
+        let uri = self.request.header.uri();
+        if uri.starts_with("http://") || uri.starts_with("https://") {
+            return uri.to_string();
         }
+        //被拦截的CONNECT请求，URI只有主机和端口
+        if self.request.header.method() == "CONNECT" {
+            return format!("{}://{}", self.stream.scheme(), uri);
+        }
+        //被Map Remote改写时Host可能保留了原来的地址，用实际连接的地址
+        let host = match self.stream.remap() {
+            Some(_) => self.stream.target(),
+            None => self.request.header.get("Host").unwrap_or(self.stream.target()),
+        };
+        format!("{}://{}{}", self.stream.scheme(), host, uri)
     }
+
+    pub fn method(&self) -> &str {
+        self.request.header.method()
+    }
+
+    pub fn status(&self) -> &HttpStatus {
+        self.response.header.status()
+    }
+
+    pub fn content_type(&self) -> &str {
+        self.response.header.get("Content-Type").unwrap_or("")
+    }
+
+    pub fn time(&self) -> SystemTime {
+        self.request.time
+    }
+
+    pub fn size(&self) -> usize {
+        self.request.size() + self.response.size()
+    }
+
+    pub fn kind(&self) -> FilterMode {
+        self.kind
+    }
+
     pub fn request(&self) -> &HttpData {
         &self.request
     }
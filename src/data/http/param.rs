@@ -0,0 +1,143 @@
+use crate::data::http::header::HttpHeaders;
+use crate::error::ProxyResult;
+
+//URL里的%XX转回原来的字节，form表单里的+表示空格
+pub fn url_decode(text: &str, plus_as_space: bool) -> String {
+    let bs = text.as_bytes();
+    let mut res = Vec::with_capacity(bs.len());
+    let mut i = 0;
+    while i < bs.len() {
+        match bs[i] {
+            b'%' if i + 2 < bs.len() => {
+                match std::str::from_utf8(&bs[i + 1..i + 3]).ok().and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                    Some(b) => {
+                        res.push(b);
+                        i += 3;
+                        continue;
+                    }
+                    None => res.push(b'%'),
+                }
+            }
+            b'+' if plus_as_space => res.push(b' '),
+            b => res.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&res).to_string()
+}
+
+//解析a=1&b=2形式的参数，查询字符串和application/x-www-form-urlencoded的body都是这种格式
+pub fn parse_query(query: &str) -> Vec<(String, String)> {
+    query.split("&").filter(|item| !item.is_empty()).map(|item| {
+        let (key, value) = item.split_once("=").unwrap_or((item, ""));
+        (url_decode(key, true), url_decode(value, true))
+    }).collect()
+}
+
+//从URL中取出查询字符串，不包括#后面的部分
+pub fn query_of(url: &str) -> &str {
+    let url = url.split("#").next().unwrap_or("");
+    url.split_once("?").map(|(_, query)| query).unwrap_or("")
+}
+
+//取出form-data; name="file"; filename="a.txt"这种头字段里的参数
+pub fn header_param(value: &str, name: &str) -> Option<String> {
+    value.split(";").skip(1).find_map(|item| {
+        let (key, value) = item.split_once("=")?;
+        if !key.trim().eq_ignore_ascii_case(name) { return None; }
+        let value = value.trim();
+        let value = value.strip_prefix("\"").and_then(|v| v.strip_suffix("\"")).unwrap_or(value);
+        Some(value.to_string())
+    })
+}
+
+//multipart/form-data里的一部分
+pub struct MultipartPart {
+    headers: HttpHeaders,
+    body: Vec<u8>,
+}
+
+impl MultipartPart {
+    pub fn headers(&self) -> &HttpHeaders {
+        &self.headers
+    }
+
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    pub fn name(&self) -> String {
+        self.headers.get("Content-Disposition").and_then(|v| header_param(v, "name")).unwrap_or_default()
+    }
+
+    pub fn filename(&self) -> Option<String> {
+        self.headers.get("Content-Disposition").and_then(|v| header_param(v, "filename"))
+    }
+}
+
+//按Content-Type里的boundary把body切成多个部分
+pub fn parse_multipart(content_type: &str, body: &[u8]) -> ProxyResult<Vec<MultipartPart>> {
+    let boundary = header_param(content_type, "boundary").ok_or("multipart缺少boundary")?;
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let mut parts = vec![];
+    let mut rest = &body[find(body, &delimiter).ok_or("找不到multipart的分隔符")? + delimiter.len()..];
+    loop {
+        //分隔符后面跟着--表示结束
+        if rest.starts_with(b"--") { break; }
+        rest = rest.strip_prefix(b"\r\n").ok_or("multipart数据错误")?;
+        let end = find(rest, &delimiter).ok_or("multipart数据不完整")?;
+        //每部分的body后面有一个属于分隔符的\r\n
+        let part = &rest[..end];
+        let part = part.strip_suffix(b"\r\n").unwrap_or(part);
+        let (head, body) = match find(part, b"\r\n\r\n") {
+            Some(pos) => (&part[..pos], &part[pos + 4..]),
+            None if part.starts_with(b"\r\n") => (&part[..0], &part[2..]),
+            None => return Err("multipart数据错误".into()),
+        };
+        let mut headers = HttpHeaders::new();
+        for line in String::from_utf8_lossy(head).split("\r\n") {
+            if let Some((key, value)) = line.split_once(":") {
+                headers.push(key.trim(), value.trim());
+            }
+        }
+        parts.push(MultipartPart { headers, body: body.to_vec() });
+        rest = &rest[end + delimiter.len()..];
+    }
+    Ok(parts)
+}
+
+fn find(bs: &[u8], pattern: &[u8]) -> Option<usize> {
+    bs.windows(pattern.len()).position(|w| w == pattern)
+}
+
+
+#[cfg(test)]
+mod test_param {
+    use crate::data::http::param::{parse_multipart, parse_query, query_of};
+
+    #[test]
+    fn test_parse_query() {
+        let query = query_of("http://a.com/s?q=rust+proxy&lang=%E4%B8%AD%E6%96%87&empty&bad=%zz#top");
+        let params = parse_query(query);
+        let expected = [("q", "rust proxy"), ("lang", "中文"), ("empty", ""), ("bad", "%zz")];
+        assert_eq!(params.len(), expected.len());
+        for ((key, value), (k, v)) in params.iter().zip(expected) {
+            assert_eq!((key.as_str(), value.as_str()), (k, v));
+        }
+    }
+
+    #[test]
+    fn test_parse_multipart() {
+        let body = b"--XyZ\r\nContent-Disposition: form-data; name=\"title\"\r\n\r\nhello\r\n\
+                     --XyZ\r\nContent-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\nContent-Type: text/plain\r\n\r\nline1\r\nline2\r\n\
+                     --XyZ--\r\n";
+        let parts = parse_multipart("multipart/form-data; boundary=XyZ", body).unwrap();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].name(), "title");
+        assert_eq!(parts[0].filename(), None);
+        assert_eq!(parts[0].body(), b"hello");
+        assert_eq!(parts[1].filename().as_deref(), Some("a.txt"));
+        assert_eq!(parts[1].headers().get("content-type"), Some("text/plain"));
+        assert_eq!(parts[1].body(), b"line1\r\nline2");
+    }
+}
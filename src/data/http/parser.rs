@@ -0,0 +1,332 @@
+use std::collections::VecDeque;
+use std::time::SystemTime;
+use crate::data::http::HttpData;
+use crate::data::StreamDirection;
+use crate::error::ProxyResult;
+
+const CRLF: &[u8] = b"\r\n";
+const CRLF_STR: &str = "\r\n";
+const HTTP_HEAD_BODY_GAP: &[u8] = b"\r\n\r\n";
+//报文头超过这个长度还没结束，就认为不是HTTP数据了
+const MAX_HEAD_LEN: usize = 64 * 1024;
+
+//报文当前的解析状态
+enum ParseState {
+    //等待报文头
+    Head,
+    //按Content-Length读取，剩余的字节数
+    Length(usize),
+    //chunked编码，等待块大小这一行
+    ChunkSize,
+    //chunked编码，当前块剩余的字节数
+    ChunkData(usize),
+    //块数据后面的CRLF
+    ChunkDataEnd,
+    //最后一个块之后的trailer字段
+    Trailer,
+    //没有长度信息的响应，读到连接断开为止
+    UntilClose,
+    //协议升级、隧道或者解析失败之后，后面的数据不再按HTTP解析
+    Opaque,
+}
+
+//正在解析的报文
+struct Message {
+    head: Vec<u8>,
+    raw: Vec<u8>,
+    body: Vec<u8>,
+    trailers: Vec<(String, String)>,
+    time: SystemTime,
+}
+
+//HTTP/1.x的增量解析器，数据可以分多次传入，解析出完整的报文后返回
+pub struct HttpParser {
+    direction: StreamDirection,
+    buf: Vec<u8>,
+    state: ParseState,
+    current: Option<Message>,
+    //当前报文第一个字节到达的时间
+    time: SystemTime,
+    //响应的解析需要知道对应请求的方法，比如HEAD请求的响应是没有body的
+    methods: VecDeque<String>,
+    //已经切分出来的报文个数
+    completed: usize,
+}
+
+impl HttpParser {
+    pub fn new(direction: StreamDirection) -> HttpParser {
+        HttpParser {
+            direction,
+            buf: vec![],
+            state: ParseState::Head,
+            current: None,
+            time: SystemTime::UNIX_EPOCH,
+            methods: VecDeque::new(),
+            completed: 0,
+        }
+    }
+
+    //告诉响应解析器下一个响应对应的请求方法
+    pub fn push_method(&mut self, method: &str) {
+        self.methods.push_back(method.to_uppercase());
+    }
+
+    //协议升级之后（比如WebSocket），连接上就不再是HTTP数据了
+    pub fn upgrade(&mut self) {
+        self.state = ParseState::Opaque;
+        self.buf.clear();
+        self.current = None;
+    }
+
+    pub fn is_opaque(&self) -> bool {
+        matches!(self.state, ParseState::Opaque)
+    }
+
+    //转发数据时只关心报文的边界，报文头能不能解析不影响转发
+    pub fn completed(&self) -> usize {
+        self.completed
+    }
+
+    pub fn push(&mut self, bs: &[u8], time: SystemTime) -> ProxyResult<Vec<HttpData>> {
+        if self.is_opaque() { return Ok(vec![]); }
+        if matches!(self.state, ParseState::Head) && self.buf.is_empty() { self.time = time; }
+        self.buf.extend_from_slice(bs);
+        let mut res = vec![];
+        loop {
+            match self.step() {
+                Ok(Step::NeedMore) => break,
+                Ok(Step::Continue) => {}
+                Ok(Step::Complete) => {
+                    //剩下的数据属于下一个报文，它的时间就是这次收到数据的时间
+                    self.time = time;
+                    if let Some(data) = self.complete() { res.push(data); }
+                }
+                Err(e) => {
+                    //数据已经乱了，后面的数据也没法再解析
+                    self.upgrade();
+                    return Err(e);
+                }
+            }
+        }
+        Ok(res)
+    }
+
+    //连接断开，没有长度信息的响应到这里才算结束
+    pub fn finish(&mut self) -> ProxyResult<Option<HttpData>> {
+        let state = std::mem::replace(&mut self.state, ParseState::Opaque);
+        match state {
+            ParseState::UntilClose => Ok(self.complete()),
+            ParseState::Head if self.buf.iter().all(|b| *b == b'\r' || *b == b'\n') => Ok(None),
+            ParseState::Opaque => Ok(None),
+            _ => Err("连接已断开，HTTP报文不完整".into()),
+        }
+    }
+
+    fn step(&mut self) -> ProxyResult<Step> {
+        match self.state {
+            ParseState::Head => self.parse_head(),
+            ParseState::Length(remaining) => {
+                let len = remaining.min(self.buf.len());
+                self.take_body(len);
+                if remaining == len { return Ok(Step::Complete); }
+                self.state = ParseState::Length(remaining - len);
+                Ok(Step::NeedMore)
+            }
+            ParseState::ChunkSize => {
+                let Some(pos) = find(&self.buf, CRLF) else { return Ok(Step::NeedMore); };
+                let line = String::from_utf8_lossy(&self.buf[..pos]).to_string();
+                //块大小后面可能带有扩展参数，用;隔开
+                let size = line.split(";").next().unwrap_or("").trim();
+                let size = usize::from_str_radix(size, 16).map_err(|_| format!("chunk大小解析失败：{}", line))?;
+                self.take_raw(pos + CRLF.len());
+                self.state = if size == 0 { ParseState::Trailer } else { ParseState::ChunkData(size) };
+                Ok(Step::Continue)
+            }
+            ParseState::ChunkData(remaining) => {
+                let len = remaining.min(self.buf.len());
+                self.take_body(len);
+                if remaining == len {
+                    self.state = ParseState::ChunkDataEnd;
+                    return Ok(Step::Continue);
+                }
+                self.state = ParseState::ChunkData(remaining - len);
+                Ok(Step::NeedMore)
+            }
+            ParseState::ChunkDataEnd => {
+                if self.buf.len() < CRLF.len() { return Ok(Step::NeedMore); }
+                if !self.buf.starts_with(CRLF) { return Err("chunk数据后缺少CRLF".into()); }
+                self.take_raw(CRLF.len());
+                self.state = ParseState::ChunkSize;
+                Ok(Step::Continue)
+            }
+            ParseState::Trailer => {
+                let Some(pos) = find(&self.buf, CRLF) else { return Ok(Step::NeedMore); };
+                let line = String::from_utf8_lossy(&self.buf[..pos]).to_string();
+                self.take_raw(pos + CRLF.len());
+                if line.is_empty() { return Ok(Step::Complete); }
+                let (key, value) = line.split_once(":").ok_or("trailer字段解析失败")?;
+                if let Some(msg) = &mut self.current {
+                    msg.trailers.push((key.trim().to_string(), value.trim().to_string()));
+                }
+                Ok(Step::Continue)
+            }
+            ParseState::UntilClose => {
+                self.take_body(self.buf.len());
+                Ok(Step::NeedMore)
+            }
+            ParseState::Opaque => Ok(Step::NeedMore),
+        }
+    }
+
+    fn parse_head(&mut self) -> ProxyResult<Step> {
+        //报文之间可能会有多余的空行，直接跳过
+        let skip = self.buf.iter().take_while(|b| **b == b'\r' || **b == b'\n').count();
+        self.buf.drain(..skip);
+        let Some(pos) = find(&self.buf, HTTP_HEAD_BODY_GAP) else {
+            if self.buf.len() > MAX_HEAD_LEN { return Err("HTTP报文头过长".into()); }
+            return Ok(Step::NeedMore);
+        };
+        let head = self.buf[..pos].to_vec();
+        let raw = self.buf.drain(..pos + HTTP_HEAD_BODY_GAP.len()).collect();
+        let head_str = String::from_utf8_lossy(&head).to_string();
+        let mut lines = head_str.split(CRLF_STR);
+        let line = lines.next().ok_or("传入的数据错误")?;
+        let mut content_length = None;
+        let mut chunked = false;
+        for line in lines {
+            let Some((key, value)) = line.split_once(":") else { continue; };
+            let (key, value) = (key.trim(), value.trim());
+            if key.eq_ignore_ascii_case("Content-Length") {
+                content_length = Some(value.parse::<usize>().map_err(|_| format!("Content-Length解析失败：{}", value))?);
+            } else if key.eq_ignore_ascii_case("Transfer-Encoding") {
+                //chunked只有在最后一个编码时才决定报文长度
+                chunked = value.rsplit(",").next().is_some_and(|v| v.trim().eq_ignore_ascii_case("chunked"));
+            }
+        }
+        self.current = Some(Message { head, raw, body: vec![], trailers: vec![], time: self.time });
+        let body_state = if chunked {
+            ParseState::ChunkSize
+        } else {
+            match content_length {
+                Some(len) => ParseState::Length(len),
+                None => ParseState::Head,
+            }
+        };
+        if let StreamDirection::ClientToServer = self.direction {
+            //请求没有长度信息的时候就没有body
+            if let ParseState::Head = body_state { return Ok(Step::Complete); }
+            self.state = body_state;
+            return Ok(Step::Continue);
+        }
+        let code = line.split(" ").nth(1).and_then(|c| c.parse::<u16>().ok()).ok_or(format!("状态码解析失败：{}", line))?;
+        //1xx的中间响应（比如100 Continue）后面还会有真正的响应，这里直接丢掉
+        if (100..200).contains(&code) && code != 101 {
+            self.current = None;
+            return Ok(Step::Continue);
+        }
+        let method = self.methods.pop_front().unwrap_or("GET".to_string());
+        if code == 101 || (method == "CONNECT" && (200..300).contains(&code)) {
+            //后面的数据是隧道或者新协议了，这个响应发出去之后就不再解析
+            self.state = ParseState::Opaque;
+            return Ok(Step::Complete);
+        }
+        if method == "HEAD" || code == 204 || code == 304 { return Ok(Step::Complete); }
+        self.state = match body_state {
+            ParseState::Head => ParseState::UntilClose,
+            state => state,
+        };
+        Ok(Step::Continue)
+    }
+
+    fn take_raw(&mut self, len: usize) {
+        let bs = self.buf.drain(..len);
+        if let Some(msg) = &mut self.current { msg.raw.extend(bs); }
+    }
+
+    fn take_body(&mut self, len: usize) {
+        let bs = self.buf.drain(..len).collect::<Vec<_>>();
+        if let Some(msg) = &mut self.current {
+            msg.raw.extend(&bs);
+            msg.body.extend(bs);
+        }
+    }
+
+    //一个报文解析完成，报文头解析失败的报文也要返回，保证请求和响应能按顺序配对
+    fn complete(&mut self) -> Option<HttpData> {
+        if !self.is_opaque() { self.state = ParseState::Head; }
+        let msg = self.current.take()?;
+        self.completed += 1;
+        Some(HttpData::from_parts(self.direction.clone(), msg.head, msg.body, msg.raw, msg.trailers, msg.time))
+    }
+}
+
+enum Step {
+    NeedMore,
+    Continue,
+    Complete,
+}
+
+fn find(bs: &[u8], pat: &[u8]) -> Option<usize> {
+    bs.windows(pat.len()).position(|w| w == pat)
+}
+
+
+#[cfg(test)]
+mod test_parser {
+    use std::time::SystemTime;
+    use crate::data::http::parser::HttpParser;
+    use crate::data::StreamDirection;
+
+    #[test]
+    fn test_pipelined_requests() {
+        let mut parser = HttpParser::new(StreamDirection::ClientToServer);
+        let raw = b"POST /a HTTP/1.1\r\nHost: a.com\r\nContent-Length: 3\r\n\r\nGETGET /b HTTP/1.1\r\nHost: a.com\r\n\r\n";
+        let mut res = vec![];
+        //一个字节一个字节地传入，模拟报文被拆成很多次读取
+        for b in raw.iter() {
+            res.extend(parser.push(&[*b], SystemTime::now()).unwrap());
+        }
+        assert_eq!(res.len(), 2);
+        assert_eq!(res[0].header().uri(), "/a");
+        assert_eq!(res[0].body().raw(), b"GET");
+        assert_eq!(res[1].header().uri(), "/b");
+        assert_eq!(res[0].size() + res[1].size(), raw.len());
+    }
+
+    #[test]
+    fn test_chunked_response() {
+        let mut parser = HttpParser::new(StreamDirection::ServerToClient);
+        parser.push_method("GET");
+        let raw = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5;ext=1\r\nhello\r\n6\r\n world\r\n0\r\nX-Checksum: 1\r\n\r\n";
+        let res = parser.push(raw, SystemTime::now()).unwrap();
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].body().raw(), b"hello world");
+        assert_eq!(res[0].header().get("X-Checksum"), Some("1"));
+        assert_eq!(res[0].raw(), raw);
+    }
+
+    #[test]
+    fn test_head_and_close_delimited_response() {
+        let mut parser = HttpParser::new(StreamDirection::ServerToClient);
+        parser.push_method("HEAD");
+        parser.push_method("GET");
+        let raw = b"HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nHTTP/1.0 200 OK\r\n\r\nuntil close";
+        let res = parser.push(raw, SystemTime::now()).unwrap();
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].body().raw(), b"");
+        let last = parser.finish().unwrap().unwrap();
+        assert_eq!(last.body().raw(), b"until close");
+    }
+
+    #[test]
+    fn test_unparsed_head() {
+        let mut parser = HttpParser::new(StreamDirection::ClientToServer);
+        let raw = b"GET /a HTTP/1.1\r\nno colon\r\n\r\nGET /b HTTP/1.1\r\n\r\n";
+        let res = parser.push(raw, SystemTime::now()).unwrap();
+        //解析失败的报文也要占一个位置，原始数据保留下来
+        assert_eq!(res.len(), 2);
+        assert_eq!(res[0].header().method(), "");
+        assert_eq!(res[0].raw(), b"GET /a HTTP/1.1\r\nno colon\r\n\r\n");
+        assert_eq!(res[1].header().uri(), "/b");
+    }
+}
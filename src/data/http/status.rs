@@ -0,0 +1,141 @@
+use std::fmt::{Display, Formatter};
+use crate::error::ProxyResult;
+
+//所有注册过的状态码写在一张表里，枚举、状态码和原因短语都从这张表生成
+macro_rules! http_status {
+    ($($name:ident = $code:literal, $reason:literal;)*) => {
+        #[derive(Clone, Debug, Eq, PartialEq)]
+        pub enum HttpStatus {
+            $($name,)*
+            //没有注册过的状态码，保留服务器返回的状态码和原因短语
+            Other(u16, String),
+        }
+
+        impl HttpStatus {
+            pub fn from_code(code: u16, reason: &str) -> HttpStatus {
+                match code {
+                    $($code => HttpStatus::$name,)*
+                    _ => HttpStatus::Other(code, reason.to_string()),
+                }
+            }
+
+            pub fn code(&self) -> u16 {
+                match self {
+                    $(HttpStatus::$name => $code,)*
+                    HttpStatus::Other(code, _) => *code,
+                }
+            }
+
+            pub fn reason(&self) -> &str {
+                match self {
+                    $(HttpStatus::$name => $reason,)*
+                    HttpStatus::Other(_, reason) => reason,
+                }
+            }
+        }
+    };
+}
+
+http_status! {
+    Continue = 100, "Continue";
+    SwitchingProtocols = 101, "Switching Protocols";
+    Processing = 102, "Processing";
+    EarlyHints = 103, "Early Hints";
+    OK = 200, "OK";
+    Created = 201, "Created";
+    Accepted = 202, "Accepted";
+    NonAuthoritativeInformation = 203, "Non-Authoritative Information";
+    NoContent = 204, "No Content";
+    ResetContent = 205, "Reset Content";
+    PartialContent = 206, "Partial Content";
+    MultiStatus = 207, "Multi-Status";
+    AlreadyReported = 208, "Already Reported";
+    ImUsed = 226, "IM Used";
+    MultipleChoices = 300, "Multiple Choices";
+    MovedPermanently = 301, "Moved Permanently";
+    Found = 302, "Found";
+    SeeOther = 303, "See Other";
+    NotModified = 304, "Not Modified";
+    UseProxy = 305, "Use Proxy";
+    TemporaryRedirect = 307, "Temporary Redirect";
+    PermanentRedirect = 308, "Permanent Redirect";
+    BadRequest = 400, "Bad Request";
+    Unauthorized = 401, "Unauthorized";
+    PaymentRequired = 402, "Payment Required";
+    Forbidden = 403, "Forbidden";
+    NotFound = 404, "Not Found";
+    MethodNotAllowed = 405, "Method Not Allowed";
+    NotAcceptable = 406, "Not Acceptable";
+    ProxyAuthenticationRequired = 407, "Proxy Authentication Required";
+    RequestTimeout = 408, "Request Timeout";
+    Conflict = 409, "Conflict";
+    Gone = 410, "Gone";
+    LengthRequired = 411, "Length Required";
+    PreconditionFailed = 412, "Precondition Failed";
+    ContentTooLarge = 413, "Content Too Large";
+    UriTooLong = 414, "URI Too Long";
+    UnsupportedMediaType = 415, "Unsupported Media Type";
+    RangeNotSatisfiable = 416, "Range Not Satisfiable";
+    ExpectationFailed = 417, "Expectation Failed";
+    ImATeapot = 418, "I'm a teapot";
+    MisdirectedRequest = 421, "Misdirected Request";
+    UnprocessableContent = 422, "Unprocessable Content";
+    Locked = 423, "Locked";
+    FailedDependency = 424, "Failed Dependency";
+    TooEarly = 425, "Too Early";
+    UpgradeRequired = 426, "Upgrade Required";
+    PreconditionRequired = 428, "Precondition Required";
+    TooManyRequests = 429, "Too Many Requests";
+    RequestHeaderFieldsTooLarge = 431, "Request Header Fields Too Large";
+    UnavailableForLegalReasons = 451, "Unavailable For Legal Reasons";
+    InternalServerError = 500, "Internal Server Error";
+    NotImplemented = 501, "Not Implemented";
+    BadGateway = 502, "Bad Gateway";
+    ServiceUnavailable = 503, "Service Unavailable";
+    GatewayTimeout = 504, "Gateway Timeout";
+    HttpVersionNotSupported = 505, "HTTP Version Not Supported";
+    VariantAlsoNegotiates = 506, "Variant Also Negotiates";
+    InsufficientStorage = 507, "Insufficient Storage";
+    LoopDetected = 508, "Loop Detected";
+    NotExtended = 510, "Not Extended";
+    NetworkAuthenticationRequired = 511, "Network Authentication Required";
+}
+
+impl HttpStatus {
+    //状态行里的状态码必须是三位数字，原因短语可以为空
+    pub fn from_stream_raw(code: &str, reason: &str) -> ProxyResult<HttpStatus> {
+        if code.len() != 3 || !code.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(format!("未知的Http状态码：{}", code).into());
+        }
+        let code = code.parse::<u16>()?;
+        if code < 100 { return Err(format!("未知的Http状态码：{}", code).into()); }
+        Ok(HttpStatus::from_code(code, reason))
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.code() >= 400
+    }
+}
+
+impl Display for HttpStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.code(), self.reason())
+    }
+}
+
+
+#[cfg(test)]
+mod test_status {
+    use crate::data::http::HttpStatus;
+
+    #[test]
+    fn test_from_stream_raw() {
+        assert_eq!(HttpStatus::from_stream_raw("404", "Not Found").unwrap(), HttpStatus::NotFound);
+        assert_eq!(HttpStatus::from_stream_raw("200", "").unwrap().to_string(), "200 OK");
+        let other = HttpStatus::from_stream_raw("599", "Network Connect Timeout").unwrap();
+        assert_eq!(other, HttpStatus::Other(599, "Network Connect Timeout".to_string()));
+        assert_eq!(other.to_string(), "599 Network Connect Timeout");
+        assert!(HttpStatus::from_stream_raw("20", "OK").is_err());
+        assert!(HttpStatus::from_stream_raw("abc", "OK").is_err());
+    }
+}
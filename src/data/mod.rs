@@ -1,8 +1,14 @@
 pub mod http;
 pub mod ui;
+pub mod search;
+pub mod session;
+pub mod har;
+pub mod snippet;
 
 use std::fmt::{Display, Formatter, Write};
-use crate::data::http::{HttpData, HttpMethod};
+use std::time::SystemTime;
+use crate::data::http::parser::HttpParser;
+use crate::data::http::{HttpData, HttpPacket};
 use crate::error::ProxyResult;
 
 #[derive(Clone)]
@@ -20,16 +26,145 @@ impl Display for StreamDirection {
     }
 }
 
+//连接的基本信息，用来拼出完整的URL
+#[derive(Clone, PartialEq)]
+pub struct StreamInfo {
+    stream_id: String,
+    scheme: String,
+    target: String,
+    //HTTPS连接和服务器协商的结果
+    tls: Option<TlsInfo>,
+    //被Map Remote改写时，改写前的URL
+    remap: Option<String>,
+    //请求或者响应被规则、断点修改过的地方
+    rewrites: Vec<String>,
+    //被拦截规则拦下，没有发给服务器
+    blocked: bool,
+    //没有解密的HTTPS隧道
+    tunneled: bool,
+}
+
+impl StreamInfo {
+    pub fn new(stream_id: impl ToString, scheme: impl ToString, target: impl ToString) -> StreamInfo {
+        StreamInfo {
+            stream_id: stream_id.to_string(),
+            scheme: scheme.to_string(),
+            target: target.to_string(),
+            tls: None,
+            remap: None,
+            rewrites: vec![],
+            blocked: false,
+            tunneled: false,
+        }
+    }
+
+    pub fn stream_id(&self) -> &str {
+        &self.stream_id
+    }
+
+    pub fn scheme(&self) -> &str {
+        &self.scheme
+    }
+
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    pub fn tls(&self) -> Option<&TlsInfo> {
+        self.tls.as_ref()
+    }
+
+    pub fn set_tls(&mut self, tls: TlsInfo) {
+        self.tls = Some(tls);
+    }
+
+    pub fn remap(&self) -> Option<&str> {
+        self.remap.as_deref()
+    }
+
+    pub fn set_remap(&mut self, remap: Option<String>) {
+        self.remap = remap;
+    }
+
+    pub fn rewrites(&self) -> &[String] {
+        &self.rewrites
+    }
+
+    pub fn set_rewrites(&mut self, rewrites: Vec<String>) {
+        self.rewrites = rewrites;
+    }
+
+    pub fn blocked(&self) -> bool {
+        self.blocked
+    }
+
+    pub fn set_blocked(&mut self, blocked: bool) {
+        self.blocked = blocked;
+    }
+
+    pub fn tunneled(&self) -> bool {
+        self.tunneled
+    }
+
+    pub fn set_tunneled(&mut self, tunneled: bool) {
+        self.tunneled = tunneled;
+    }
+
+    //报文和客户端发出、服务器返回的不一样
+    pub fn modified(&self) -> bool {
+        self.remap.is_some() || !self.rewrites.is_empty()
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub struct TlsInfo {
+    sni: String,
+    version: String,
+    cipher: String,
+    //没有协商ALPN时为空
+    alpn: String,
+}
+
+impl TlsInfo {
+    pub fn new(sni: impl ToString, version: impl ToString, cipher: impl ToString, alpn: impl ToString) -> TlsInfo {
+        TlsInfo { sni: sni.to_string(), version: version.to_string(), cipher: cipher.to_string(), alpn: alpn.to_string() }
+    }
+
+    pub fn sni(&self) -> &str {
+        &self.sni
+    }
+
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    pub fn cipher(&self) -> &str {
+        &self.cipher
+    }
+
+    pub fn alpn(&self) -> &str {
+        &self.alpn
+    }
+}
+
+//代理发给数据处理端的事件，先发连接信息，然后是两个方向的数据
+pub enum ProxyEvent {
+    //两种事件都比较大，装箱之后通道里的每个事件只占一个指针
+    Open(Box<StreamInfo>),
+    Data(Box<ProxyData>),
+}
+
 pub struct ProxyData {
     stream_id: String,
     direction: StreamDirection,
     buffer: [u8; 4096],
     len: usize,
+    time: SystemTime,
 }
 
 impl ProxyData {
     pub fn new(direction: StreamDirection, buffer: [u8; 4096], len: usize, id: String) -> Self {
-        Self { direction, buffer, len, stream_id: id }
+        Self { direction, buffer, len, stream_id: id, time: SystemTime::now() }
     }
 
     pub fn direction(&self) -> &StreamDirection {
@@ -51,54 +186,82 @@ impl ProxyData {
 
 
 pub struct HttpTcpData {
-    stream_id: String,
-    req_raw: Vec<u8>,
-    res_raw: Vec<u8>,
+    info: StreamInfo,
+    req_parser: HttpParser,
+    res_parser: HttpParser,
     reqs: Vec<HttpData>,
     ress: Vec<HttpData>,
+    //两个方向都断开之后，这个连接的数据就可以清理掉了
+    req_closed: bool,
+    res_closed: bool,
 }
 
 impl HttpTcpData {
-    fn push_req(&mut self, raw: [u8; 4096], len: usize) -> ProxyResult<()> {
-        for md in HttpMethod::method_bytes() {
-            if raw.starts_with(md) && self.req_raw.len() != 0 {
-                let bs = self.req_raw.drain(..self.req_raw.len()).collect::<Vec<_>>();
-                self.reqs.push(HttpData::from_bytes(bs, StreamDirection::ClientToServer)?);
-                break;
-            }
+    fn push_req(&mut self, raw: &[u8], time: SystemTime) -> ProxyResult<()> {
+        if raw.is_empty() {
+            self.req_closed = true;
+            self.reqs.extend(self.req_parser.finish()?);
+            return Ok(());
+        }
+        for req in self.req_parser.push(raw, time)? {
+            //响应的解析需要知道请求方法
+            self.res_parser.push_method(req.header().method());
+            self.reqs.push(req);
         }
-        self.req_raw.extend(&raw[..len]);
         Ok(())
     }
 
-    fn push_res(&mut self, raw: [u8; 4096], len: usize) -> ProxyResult<()> {
-        if raw.starts_with(b"HTTP/1.1") && self.res_raw.len() != 0 {
-            let bs = self.res_raw.drain(..self.res_raw.len()).collect::<Vec<_>>();
-            self.ress.push(HttpData::from_bytes(bs, StreamDirection::ServerToClient)?);
+    fn push_res(&mut self, raw: &[u8], time: SystemTime) -> ProxyResult<()> {
+        if raw.is_empty() {
+            self.res_closed = true;
+            self.ress.extend(self.res_parser.finish()?);
+            return Ok(());
         }
-        self.res_raw.extend(&raw[..len]);
+        self.ress.extend(self.res_parser.push(raw, time)?);
+        //服务器同意升级协议之后，请求方向的数据也不再是HTTP了
+        if self.res_parser.is_opaque() { self.req_parser.upgrade(); }
         Ok(())
     }
 
+    //长度为0的数据表示这个方向的连接已经断开
     pub fn push(&mut self, pd: ProxyData) -> ProxyResult<()> {
+        let raw = &pd.buffer[..pd.len];
         match pd.direction {
-            StreamDirection::ClientToServer => self.push_req(pd.buffer, pd.len),
-            StreamDirection::ServerToClient => self.push_res(pd.buffer, pd.len)
+            StreamDirection::ClientToServer => self.push_req(raw, pd.time),
+            StreamDirection::ServerToClient => self.push_res(raw, pd.time)
+        }
+    }
+
+    pub fn closed(&self) -> bool {
+        self.req_closed && self.res_closed
+    }
+
+    //配对阶段：同一个连接上的请求和响应是按顺序一问一答的，这里按顺序把它们配成一对
+    pub fn packets(&mut self) -> Vec<HttpPacket> {
+        //被拦截后直接断开的请求没有响应，连接断开时也要记录下来
+        if self.closed() && self.info.blocked() {
+            self.ress.resize_with(self.reqs.len().max(self.ress.len()), HttpData::new);
         }
+        let len = self.reqs.len().min(self.ress.len());
+        let reqs = self.reqs.drain(..len);
+        let ress = self.ress.drain(..len);
+        reqs.zip(ress).map(|(req, res)| HttpPacket::from_data(self.info.clone(), req, res)).collect()
     }
 
-    pub fn new() -> Self {
+    pub fn new(info: StreamInfo) -> Self {
         Self {
-            stream_id: "".to_string(),
-            req_raw: vec![],
-            res_raw: vec![],
+            info,
+            req_parser: HttpParser::new(StreamDirection::ClientToServer),
+            res_parser: HttpParser::new(StreamDirection::ServerToClient),
             reqs: vec![],
             ress: vec![],
+            req_closed: false,
+            res_closed: false,
         }
     }
 }
 
-#[derive(Eq, PartialEq)]
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
 pub enum FilterMode {
     None,
     XHR,
@@ -116,6 +279,68 @@ impl FilterMode {
         [FilterMode::None, FilterMode::XHR, FilterMode::Document, FilterMode::Css, FilterMode::Js,
             FilterMode::Font, FilterMode::Image, FilterMode::Media, FilterMode::Ws]
     }
+
+    //判断请求的资源类型，浏览器带的Sec-Fetch-Dest最准确，其次是响应的Content-Type，最后看URL的扩展名和Accept
+    pub fn classify(packet: &HttpPacket) -> FilterMode {
+        let req = packet.request().header();
+        let upgrade = req.get("Upgrade").unwrap_or("");
+        if packet.status().code() == 101 || upgrade.eq_ignore_ascii_case("websocket") { return FilterMode::Ws; }
+        let mode = match req.get("Sec-Fetch-Dest").unwrap_or("").to_lowercase().as_str() {
+            "document" | "iframe" | "frame" => FilterMode::Document,
+            "style" => FilterMode::Css,
+            "script" | "worker" | "sharedworker" | "serviceworker" => FilterMode::Js,
+            "font" => FilterMode::Font,
+            "image" => FilterMode::Image,
+            "audio" | "video" | "track" => FilterMode::Media,
+            "websocket" => FilterMode::Ws,
+            //fetch和XMLHttpRequest发出的请求没有明确的目标
+            "empty" => FilterMode::XHR,
+            _ => FilterMode::None,
+        };
+        if mode != FilterMode::None { return mode; }
+        if req.get("X-Requested-With").is_some_and(|v| v.eq_ignore_ascii_case("XMLHttpRequest")) { return FilterMode::XHR; }
+        let mode = FilterMode::from_mime(packet.content_type());
+        if mode != FilterMode::None { return mode; }
+        let mode = FilterMode::from_extension(&packet.url());
+        if mode != FilterMode::None { return mode; }
+        //Accept里只看第一个类型
+        FilterMode::from_mime(req.get("Accept").unwrap_or("").split(",").next().unwrap_or(""))
+    }
+
+    fn from_mime(content_type: &str) -> FilterMode {
+        let mime = content_type.split(";").next().unwrap_or("").trim().to_lowercase();
+        match mime.as_str() {
+            "text/html" | "application/xhtml+xml" => FilterMode::Document,
+            "text/css" => FilterMode::Css,
+            "application/vnd.apple.mpegurl" | "application/x-mpegurl" | "application/dash+xml" => FilterMode::Media,
+            m if m.contains("javascript") || m.contains("ecmascript") => FilterMode::Js,
+            m if m.starts_with("font/") || m.starts_with("application/font-") || m.contains("woff") => FilterMode::Font,
+            m if m.starts_with("image/") => FilterMode::Image,
+            m if m.starts_with("audio/") || m.starts_with("video/") => FilterMode::Media,
+            m if m.contains("json") || m.ends_with("/xml") || m.ends_with("+xml") => FilterMode::XHR,
+            _ => FilterMode::None,
+        }
+    }
+
+    fn from_extension(url: &str) -> FilterMode {
+        let path = url.split(['?', '#']).next().unwrap_or("");
+        let name = path.rsplit("/").next().unwrap_or("");
+        let Some((_, ext)) = name.rsplit_once(".") else { return FilterMode::None; };
+        match ext.to_lowercase().as_str() {
+            "html" | "htm" | "xhtml" => FilterMode::Document,
+            "css" => FilterMode::Css,
+            "js" | "mjs" => FilterMode::Js,
+            "woff" | "woff2" | "ttf" | "otf" | "eot" => FilterMode::Font,
+            "png" | "jpg" | "jpeg" | "gif" | "webp" | "svg" | "ico" | "bmp" | "avif" => FilterMode::Image,
+            "mp4" | "webm" | "mp3" | "m4a" | "aac" | "ogg" | "wav" | "flac" | "m3u8" | "ts" | "flv" => FilterMode::Media,
+            _ => FilterMode::None,
+        }
+    }
+
+    //选中“无”时显示全部
+    pub fn matches(&self, packet: &HttpPacket) -> bool {
+        *self == FilterMode::None || *self == packet.kind()
+    }
 }
 
 impl Display for FilterMode {
@@ -132,4 +357,36 @@ impl Display for FilterMode {
             FilterMode::Ws => f.write_str("套接字")
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test_filter {
+    use std::time::SystemTime;
+    use crate::data::http::{HttpData, HttpPacket};
+    use crate::data::{FilterMode, StreamDirection, StreamInfo};
+
+    fn packet(req_head: &str, res_head: &str) -> HttpPacket {
+        let data = |direction, head: &str| {
+            HttpData::from_parts(direction, head.as_bytes().to_vec(), vec![], vec![], vec![], SystemTime::now())
+        };
+        HttpPacket::from_data(StreamInfo::new("1", "http", "a.com:80"),
+                              data(StreamDirection::ClientToServer, req_head), data(StreamDirection::ServerToClient, res_head))
+    }
+
+    #[test]
+    fn test_classify() {
+        let p = packet("GET /app.js HTTP/1.1\r\nSec-Fetch-Dest: script", "HTTP/1.1 200 OK\r\nContent-Type: text/plain");
+        assert_eq!(p.kind(), FilterMode::Js);
+        let p = packet("GET /api HTTP/1.1\r\nX-Requested-With: XMLHttpRequest", "HTTP/1.1 200 OK\r\nContent-Type: text/html");
+        assert_eq!(p.kind(), FilterMode::XHR);
+        let p = packet("GET /logo.PNG?v=1 HTTP/1.1", "HTTP/1.1 304 Not Modified");
+        assert_eq!(p.kind(), FilterMode::Image);
+        let p = packet("GET /f HTTP/1.1", "HTTP/1.1 200 OK\r\nContent-Type: font/woff2");
+        assert_eq!(p.kind(), FilterMode::Font);
+        let p = packet("GET /chat HTTP/1.1\r\nUpgrade: websocket", "HTTP/1.1 101 Switching Protocols");
+        assert_eq!(p.kind(), FilterMode::Ws);
+        assert!(FilterMode::None.matches(&p) && !FilterMode::Css.matches(&p));
+        let p = packet("GET / HTTP/1.1\r\nAccept: text/html,application/xhtml+xml", "HTTP/1.1 200 OK");
+        assert_eq!(p.kind(), FilterMode::Document);
+    }
+}
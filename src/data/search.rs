@@ -0,0 +1,229 @@
+use regex::{Regex, RegexBuilder};
+use crate::data::http::{split_url, HttpData, HttpPacket};
+use crate::error::ProxyResult;
+
+//搜索框里的查询，多个条件用空格隔开，全部满足才算匹配，例如：
+//host:api.example.com status:>=400 method:POST body~"token" -type:image
+//字段名后面跟:表示包含（不区分大小写），跟~表示正则匹配，前面加-表示取反，没有字段名时搜索URL、请求头和body
+pub struct SearchQuery {
+    terms: Vec<Term>,
+}
+
+struct Term {
+    field: Field,
+    matcher: Matcher,
+    negate: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Field {
+    Any,
+    Host,
+    Url,
+    Path,
+    Method,
+    Status,
+    Type,
+    Header,
+    Body,
+    Size,
+}
+
+enum Matcher {
+    Contains(String),
+    Regex(Regex),
+    //数字比较，状态码和大小可以用
+    Compare(&'static str, u64),
+    //4xx这种按状态码的类别匹配
+    StatusClass(u16),
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Field> {
+        match name.to_lowercase().as_str() {
+            "host" | "domain" => Some(Field::Host),
+            "url" => Some(Field::Url),
+            "path" => Some(Field::Path),
+            "method" => Some(Field::Method),
+            "status" | "code" => Some(Field::Status),
+            "type" | "mime" => Some(Field::Type),
+            "header" => Some(Field::Header),
+            "body" => Some(Field::Body),
+            "size" => Some(Field::Size),
+            _ => None,
+        }
+    }
+}
+
+impl SearchQuery {
+    pub fn parse(text: &str) -> ProxyResult<SearchQuery> {
+        let mut terms = vec![];
+        for (token, quoted) in tokenize(text) {
+            let (negate, token) = match token.strip_prefix("-") {
+                Some(rest) if !quoted && !rest.is_empty() => (true, rest.to_string()),
+                _ => (false, token),
+            };
+            terms.push(Term::parse(&token, quoted, negate)?);
+        }
+        Ok(SearchQuery { terms })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    pub fn matches(&self, packet: &HttpPacket) -> bool {
+        self.terms.iter().all(|term| term.matches(packet) != term.negate)
+    }
+}
+
+impl Term {
+    fn parse(token: &str, quoted: bool, negate: bool) -> ProxyResult<Term> {
+        //用引号括起来的整体当作普通文本
+        let pos = if quoted { None } else { token.find([':', '~']) };
+        let (field, op, value) = match pos.and_then(|p| Field::from_name(&token[..p]).map(|f| (f, p))) {
+            Some((field, p)) => (field, &token[p..p + 1], &token[p + 1..]),
+            None => match token.strip_prefix("~") {
+                Some(pattern) if !quoted => (Field::Any, "~", pattern),
+                _ => (Field::Any, ":", token),
+            },
+        };
+        let matcher = if op == "~" {
+            Matcher::Regex(RegexBuilder::new(value).case_insensitive(true).build()?)
+        } else if field == Field::Status || field == Field::Size {
+            parse_number(field, value)?
+        } else {
+            Matcher::Contains(value.to_lowercase())
+        };
+        Ok(Term { field, matcher, negate })
+    }
+
+    fn matches(&self, packet: &HttpPacket) -> bool {
+        match &self.matcher {
+            Matcher::Compare(op, expected) => {
+                let actual = if self.field == Field::Size { packet.size() as u64 } else { packet.status().code() as u64 };
+                match *op {
+                    ">=" => actual >= *expected,
+                    "<=" => actual <= *expected,
+                    ">" => actual > *expected,
+                    "<" => actual < *expected,
+                    _ => actual == *expected,
+                }
+            }
+            Matcher::StatusClass(class) => packet.status().code() / 100 == *class,
+            Matcher::Contains(needle) => texts(self.field, packet).iter().any(|text| text.to_lowercase().contains(needle)),
+            Matcher::Regex(regex) => texts(self.field, packet).iter().any(|text| regex.is_match(text)),
+        }
+    }
+}
+
+fn parse_number(field: Field, value: &str) -> ProxyResult<Matcher> {
+    let value = value.trim();
+    //用户输入的可能是任意字符，按字符取，不能直接按字节切
+    if field == Field::Status && value.chars().count() == 3 && value.get(1..).is_some_and(|rest| rest.eq_ignore_ascii_case("xx")) {
+        let class = value[..1].parse::<u16>().map_err(|_| format!("状态码格式错误：{}", value))?;
+        return Ok(Matcher::StatusClass(class));
+    }
+    let op = [">=", "<=", ">", "<", "="].into_iter().find(|op| value.starts_with(op)).unwrap_or("=");
+    let number = value.strip_prefix(op).unwrap_or(value).trim().to_lowercase();
+    //大小可以带k和m的单位
+    let (number, unit) = match number.strip_suffix("k").or(number.strip_suffix("kb")) {
+        Some(n) => (n.to_string(), 1024),
+        None => match number.strip_suffix("m").or(number.strip_suffix("mb")) {
+            Some(n) => (n.to_string(), 1024 * 1024),
+            None => (number.clone(), 1),
+        },
+    };
+    let number = number.trim().parse::<u64>().map_err(|_| format!("数字格式错误：{}", value))?;
+    let number = number.checked_mul(unit).ok_or(format!("数字太大：{}", value))?;
+    Ok(Matcher::Compare(op, number))
+}
+
+//取出字段对应的文本，请求和响应都参与匹配
+fn texts(field: Field, packet: &HttpPacket) -> Vec<String> {
+    let url = packet.url();
+    let headers = |data: &HttpData| data.header().keys().iter().map(|(k, v)| format!("{}: {}", k, v)).collect::<Vec<_>>();
+    let bodies = || [packet.request(), packet.response()].map(|data| String::from_utf8_lossy(data.body().decoded()).to_string());
+    match field {
+        Field::Host => vec![split_url(&url).map(|(_, host, _)| host.to_string()).unwrap_or_default()],
+        Field::Path => vec![split_url(&url).map(|(_, _, path)| path).unwrap_or_default()],
+        Field::Method => vec![packet.method().to_string()],
+        Field::Type => vec![packet.content_type().to_string()],
+        Field::Header => [headers(packet.request()), headers(packet.response())].concat(),
+        Field::Body => bodies().to_vec(),
+        Field::Any => {
+            let mut texts = vec![url];
+            texts.extend(headers(packet.request()));
+            texts.extend(headers(packet.response()));
+            texts.extend(bodies());
+            texts
+        }
+        //数字字段的正则匹配的是数字本身
+        Field::Status => vec![packet.status().code().to_string()],
+        Field::Size => vec![packet.size().to_string()],
+        Field::Url => vec![url],
+    }
+}
+
+//按空格拆分，双引号里的空格不拆，同时记录是否以引号开头
+fn tokenize(text: &str) -> Vec<(String, bool)> {
+    let mut tokens = vec![];
+    let mut token = String::new();
+    let mut in_quote = false;
+    let mut quoted = false;
+    for c in text.chars() {
+        match c {
+            '"' => {
+                if token.is_empty() { quoted = true; }
+                in_quote = !in_quote;
+            }
+            c if c.is_whitespace() && !in_quote => {
+                if !token.is_empty() || quoted { tokens.push((std::mem::take(&mut token), quoted)); }
+                quoted = false;
+            }
+            c => token.push(c),
+        }
+    }
+    if !token.is_empty() || quoted { tokens.push((token, quoted)); }
+    tokens.retain(|(token, _)| !token.is_empty());
+    tokens
+}
+
+
+#[cfg(test)]
+mod test_search {
+    use std::time::SystemTime;
+    use crate::data::http::{HttpData, HttpPacket};
+    use crate::data::search::SearchQuery;
+    use crate::data::{StreamDirection, StreamInfo};
+
+    fn packet() -> HttpPacket {
+        let req = b"POST /login?next=/home HTTP/1.1\r\nHost: api.example.com\r\nContent-Type: application/json".to_vec();
+        let res = b"HTTP/1.1 401 Unauthorized\r\nContent-Type: application/json\r\nX-Trace: abc".to_vec();
+        let req = HttpData::from_parts(StreamDirection::ClientToServer, req, b"{\"token\":\"T0K\"}".to_vec(), vec![], vec![], SystemTime::now());
+        let res = HttpData::from_parts(StreamDirection::ServerToClient, res, b"{\"error\":\"denied\"}".to_vec(), vec![], vec![], SystemTime::now());
+        HttpPacket::from_data(StreamInfo::new("1", "http", "api.example.com:80"), req, res)
+    }
+
+    #[test]
+    fn test_search() {
+        let packet = packet();
+        let matches = |text: &str| SearchQuery::parse(text).unwrap().matches(&packet);
+        assert!(matches("host:api.example.com status:>=400 method:POST body~\"t0k\""));
+        assert!(matches("status:4xx path:/login -type:image"));
+        assert!(matches("x-trace \"denied\""));
+        assert!(matches("header~^x-trace:\\s+abc$ size:<1k"));
+        assert!(!matches("status:<400"));
+        assert!(matches("status~^4\\d1$ size~^\\d+$"));
+        assert!(!matches("status~^5"));
+        assert!(!matches("status~login"));
+        assert!(!matches("size~example"));
+        assert!(!matches("-method:post"));
+        assert!(!matches("body:\"not here\""));
+        assert!(SearchQuery::parse("body~(").is_err());
+        assert!(SearchQuery::parse("status:>=abc").is_err());
+        assert!(SearchQuery::parse("status:中").is_err());
+        assert!(SearchQuery::parse("status:éx").is_err());
+        assert!(SearchQuery::parse("size:>99999999999999m").is_err());
+    }
+}
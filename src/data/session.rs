@@ -0,0 +1,161 @@
+use std::time::{Duration, SystemTime};
+use crate::data::http::parser::HttpParser;
+use crate::data::http::{HttpData, HttpPacket};
+use crate::data::{StreamDirection, StreamInfo, TlsInfo};
+use crate::error::ProxyResult;
+
+//会话文件的格式：
+//魔数PXSESSION，2字节的版本号，4字节的请求个数，然后是每个请求：
+//  连接信息：4字节的字段个数，每个字段是键和值两个字符串，以后增加字段不用改版本号
+//  请求和响应：8字节的时间（纳秒），原始数据
+//字符串和原始数据都是4字节的长度加上内容，数字都是大端
+const MAGIC: &[u8] = b"PXSESSION";
+const VERSION: u16 = 1;
+
+pub fn encode_session(packets: &[HttpPacket]) -> Vec<u8> {
+    let mut res = MAGIC.to_vec();
+    res.extend(VERSION.to_be_bytes());
+    res.extend((packets.len() as u32).to_be_bytes());
+    for packet in packets {
+        let stream = packet.stream();
+        let rewrites = stream.rewrites().join("\n");
+        let mut fields = vec![("id", stream.stream_id()), ("scheme", stream.scheme()), ("target", stream.target())];
+        if let Some(remap) = stream.remap() {
+            fields.push(("remap", remap));
+        }
+        if !rewrites.is_empty() {
+            fields.push(("rewrites", &rewrites));
+        }
+        if stream.blocked() {
+            fields.push(("blocked", "1"));
+        }
+        if stream.tunneled() {
+            fields.push(("tunneled", "1"));
+        }
+        if let Some(tls) = stream.tls() {
+            fields.extend([("tls.sni", tls.sni()), ("tls.version", tls.version()), ("tls.cipher", tls.cipher()), ("tls.alpn", tls.alpn())]);
+        }
+        res.extend((fields.len() as u32).to_be_bytes());
+        for (key, value) in fields {
+            write_bytes(&mut res, key.as_bytes());
+            write_bytes(&mut res, value.as_bytes());
+        }
+        for data in [packet.request(), packet.response()] {
+            let nanos = data.time().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_nanos() as u64;
+            res.extend(nanos.to_be_bytes());
+            write_bytes(&mut res, data.raw());
+        }
+    }
+    res
+}
+
+//请求和响应都保存的是原始数据，读取时重新解析一遍，和抓包时看到的完全一样
+pub fn decode_session(bs: &[u8]) -> ProxyResult<Vec<HttpPacket>> {
+    let mut reader = Reader { bs, pos: 0 };
+    if reader.take(MAGIC.len())? != MAGIC { return Err("不是会话文件".into()); }
+    let version = u16::from_be_bytes(reader.take(2)?.try_into()?);
+    if version > VERSION { return Err(format!("不支持的会话文件版本：{}", version).into()); }
+    let count = reader.u32()?;
+    let mut packets = vec![];
+    for _ in 0..count {
+        let mut fields = vec![];
+        for _ in 0..reader.u32()? {
+            fields.push((reader.string()?, reader.string()?));
+        }
+        let field = |key: &str| fields.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str()).unwrap_or("");
+        let mut stream = StreamInfo::new(field("id"), field("scheme"), field("target"));
+        if !field("tls.sni").is_empty() || !field("tls.version").is_empty() {
+            stream.set_tls(TlsInfo::new(field("tls.sni"), field("tls.version"), field("tls.cipher"), field("tls.alpn")));
+        }
+        if !field("remap").is_empty() { stream.set_remap(Some(field("remap").to_string())); }
+        stream.set_rewrites(field("rewrites").lines().map(|line| line.to_string()).collect());
+        stream.set_blocked(field("blocked") == "1");
+        stream.set_tunneled(field("tunneled") == "1");
+        let request = reader.data(StreamDirection::ClientToServer, None)?;
+        let response = reader.data(StreamDirection::ServerToClient, Some(request.header().method()))?;
+        packets.push(HttpPacket::from_data(stream, request, response));
+    }
+    Ok(packets)
+}
+
+fn write_bytes(res: &mut Vec<u8>, bs: &[u8]) {
+    res.extend((bs.len() as u32).to_be_bytes());
+    res.extend(bs);
+}
+
+struct Reader<'a> {
+    bs: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> ProxyResult<&'a [u8]> {
+        let end = self.pos.checked_add(len).filter(|end| *end <= self.bs.len()).ok_or("会话文件已损坏")?;
+        let res = &self.bs[self.pos..end];
+        self.pos = end;
+        Ok(res)
+    }
+
+    fn u32(&mut self) -> ProxyResult<u32> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn bytes(&mut self) -> ProxyResult<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    fn string(&mut self) -> ProxyResult<String> {
+        Ok(String::from_utf8(self.bytes()?.to_vec())?)
+    }
+
+    fn data(&mut self, direction: StreamDirection, method: Option<&str>) -> ProxyResult<HttpData> {
+        let nanos = u64::from_be_bytes(self.take(8)?.try_into()?);
+        let time = SystemTime::UNIX_EPOCH + Duration::from_nanos(nanos);
+        let raw = self.bytes()?;
+        if raw.is_empty() { return Ok(HttpData::new()); }
+        let mut parser = HttpParser::new(direction);
+        if let Some(method) = method { parser.push_method(method); }
+        let mut datas = parser.push(raw, time)?;
+        //没有长度的响应要等连接断开才算结束
+        if datas.is_empty() { datas.extend(parser.finish()?); }
+        Ok(datas.into_iter().next().ok_or("会话里的报文解析失败")?)
+    }
+}
+
+
+#[cfg(test)]
+mod test_session {
+    use std::time::{Duration, SystemTime};
+    use crate::data::http::parser::HttpParser;
+    use crate::data::http::HttpPacket;
+    use crate::data::session::{decode_session, encode_session};
+    use crate::data::{StreamDirection, StreamInfo, TlsInfo};
+
+    #[test]
+    fn test_round_trip() {
+        let time = SystemTime::UNIX_EPOCH + Duration::from_nanos(1_700_000_000_123_456_789);
+        let req = b"POST /a HTTP/1.1\r\nHost: a.com\r\nContent-Length: 3\r\n\r\nabc";
+        let res = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nhi\r\n0\r\n\r\n";
+        let request = HttpParser::new(StreamDirection::ClientToServer).push(req, time).unwrap().remove(0);
+        let mut parser = HttpParser::new(StreamDirection::ServerToClient);
+        parser.push_method("POST");
+        let response = parser.push(res, time + Duration::from_millis(30)).unwrap().remove(0);
+        let mut stream = StreamInfo::new("s1", "https", "a.com:443");
+        stream.set_tls(TlsInfo::new("a.com", "TLSv1_3", "TLS13_AES_128_GCM_SHA256", "http/1.1"));
+        let bs = encode_session(&[HttpPacket::from_data(stream, request, response)]);
+
+        let packets = decode_session(&bs).unwrap();
+        assert_eq!(packets.len(), 1);
+        let packet = &packets[0];
+        assert_eq!(packet.url(), "https://a.com/a");
+        assert_eq!(packet.stream().stream_id(), "s1");
+        assert_eq!(packet.stream().tls().unwrap().cipher(), "TLS13_AES_128_GCM_SHA256");
+        assert_eq!(packet.request().raw(), req);
+        assert_eq!(packet.request().time(), time);
+        assert_eq!(packet.response().raw(), res);
+        assert_eq!(packet.response().body().decoded(), b"hi");
+        assert!(decode_session(&bs[..bs.len() - 1]).is_err());
+        assert!(decode_session(b"not a session").is_err());
+    }
+}
@@ -0,0 +1,184 @@
+use std::fmt::Write;
+use crate::data::http::HttpPacket;
+
+//把抓到的请求转换成各种工具的代码，方便重放
+
+//这些字段由工具自己生成，body已经去掉了chunked编码
+const SKIP_HEADERS: [&str; 4] = ["Host", "Content-Length", "Transfer-Encoding", "Proxy-Connection"];
+
+fn headers(packet: &HttpPacket) -> Vec<(&str, &str)> {
+    packet.request().header().keys().iter().filter(|(key, _)| !SKIP_HEADERS.iter().any(|h| h.eq_ignore_ascii_case(key))).collect()
+}
+
+//body保持网络上的样子，带着Content-Encoding一起发出去
+fn body(packet: &HttpPacket) -> &[u8] {
+    packet.request().body().raw()
+}
+
+pub fn to_curl(packet: &HttpPacket) -> String {
+    let mut res = "curl".to_string();
+    match packet.method() {
+        "GET" => {}
+        "HEAD" => res.push_str(" --head"),
+        method => { let _ = write!(res, " -X {}", method); }
+    }
+    let _ = write!(res, " {}", shell_quote(packet.url().as_bytes()));
+    for (key, value) in headers(packet) {
+        let _ = write!(res, " \\\n  -H {}", shell_quote(format!("{}: {}", key, value).as_bytes()));
+    }
+    if !body(packet).is_empty() {
+        let _ = write!(res, " \\\n  --data-binary {}", shell_quote(body(packet)));
+    }
+    res
+}
+
+pub fn to_wget(packet: &HttpPacket) -> String {
+    let mut res = format!("wget --method={} -O -", packet.method());
+    for (key, value) in headers(packet) {
+        let _ = write!(res, " \\\n  --header={}", shell_quote(format!("{}: {}", key, value).as_bytes()));
+    }
+    if !body(packet).is_empty() {
+        let _ = write!(res, " \\\n  --body-data={}", shell_quote(body(packet)));
+    }
+    let _ = write!(res, " \\\n  {}", shell_quote(packet.url().as_bytes()));
+    res
+}
+
+pub fn to_python(packet: &HttpPacket) -> String {
+    let mut res = "import requests\n\nheaders = {\n".to_string();
+    for (key, value) in headers(packet) {
+        let _ = writeln!(res, "    {}: {},", python_quote(key.as_bytes()), python_quote(value.as_bytes()));
+    }
+    res.push_str("}\n");
+    let data = if body(packet).is_empty() { "" } else { ", data=data" };
+    if !data.is_empty() { let _ = writeln!(res, "data = {}", python_quote(body(packet))); }
+    let _ = writeln!(res, "\nresponse = requests.request({}, {}, headers=headers{})",
+                     python_quote(packet.method().as_bytes()), python_quote(packet.url().as_bytes()), data);
+    res.push_str("print(response.status_code)\nprint(response.text)\n");
+    res
+}
+
+pub fn to_reqwest(packet: &HttpPacket) -> String {
+    let url = format!("{:?}", packet.url());
+    let request = match packet.method() {
+        "GET" | "POST" | "PUT" | "DELETE" | "HEAD" | "PATCH" => format!("{}({})", packet.method().to_lowercase(), url),
+        method => format!("request(reqwest::Method::from_bytes(b{:?})?, {})", method, url),
+    };
+    let mut res = format!("let client = reqwest::Client::new();\nlet response = client\n    .{}\n", request);
+    for (key, value) in headers(packet) {
+        let _ = writeln!(res, "    .header({:?}, {:?})", key, value);
+    }
+    if !body(packet).is_empty() {
+        match std::str::from_utf8(body(packet)) {
+            Ok(text) => { let _ = writeln!(res, "    .body({:?})", text); }
+            Err(_) => { let _ = writeln!(res, "    .body(&{}[..])", rust_bytes(body(packet))); }
+        }
+    }
+    res.push_str("    .send()\n    .await?;\nprintln!(\"{}\", response.status());\nprintln!(\"{}\", response.text().await?);\n");
+    res
+}
+
+//HTTP/1.1的原始报文，请求行改成只有路径的形式，Content-Length按body重新计算
+pub fn to_raw(packet: &HttpPacket) -> String {
+    let url = packet.url();
+    let (host, path) = match url.split_once("://").map(|(_, rest)| rest) {
+        Some(rest) => match rest.find(['/', '?']) {
+            Some(pos) => (&rest[..pos], rest[pos..].to_string()),
+            None => (rest, "/".to_string()),
+        },
+        None => ("", url.clone()),
+    };
+    let mut res = format!("{} {} HTTP/1.1\r\nHost: {}\r\n", packet.method(), path, host);
+    for (key, value) in headers(packet) {
+        let _ = write!(res, "{}: {}\r\n", key, value);
+    }
+    if !body(packet).is_empty() {
+        let _ = write!(res, "Content-Length: {}\r\n", body(packet).len());
+    }
+    res.push_str("\r\n");
+    res.push_str(&String::from_utf8_lossy(body(packet)));
+    res
+}
+
+//单引号里除了单引号本身都不用转义，不是UTF-8或者有控制字符时用$'...'的形式
+fn shell_quote(bs: &[u8]) -> String {
+    let plain = std::str::from_utf8(bs).ok().filter(|s| !s.chars().any(|c| c.is_control() && c != '\n'));
+    if let Some(text) = plain {
+        return format!("'{}'", text.replace("'", "'\\''"));
+    }
+    let mut res = "$'".to_string();
+    for b in bs {
+        match b {
+            b'\'' | b'\\' => { res.push('\\'); res.push(*b as char); }
+            0x20..0x7f => res.push(*b as char),
+            _ => { let _ = write!(res, "\\x{:02x}", b); }
+        }
+    }
+    res.push('\'');
+    res
+}
+
+//是UTF-8时生成字符串，否则生成bytes
+fn python_quote(bs: &[u8]) -> String {
+    let (prefix, text) = match std::str::from_utf8(bs) {
+        Ok(text) => ("", text.chars().map(|c| c.to_string()).collect::<Vec<_>>()),
+        Err(_) => ("b", bs.iter().map(|b| if b.is_ascii() { (*b as char).to_string() } else { format!("\\x{:02x}", b) }).collect()),
+    };
+    let mut res = format!("{}'", prefix);
+    for c in text {
+        match c.as_str() {
+            "'" => res.push_str("\\'"),
+            "\\" => res.push_str("\\\\"),
+            "\n" => res.push_str("\\n"),
+            "\r" => res.push_str("\\r"),
+            "\t" => res.push_str("\\t"),
+            s if s.len() == 1 && s.as_bytes()[0] < 0x20 => { let _ = write!(res, "\\x{:02x}", s.as_bytes()[0]); }
+            s => res.push_str(s),
+        }
+    }
+    res.push('\'');
+    res
+}
+
+fn rust_bytes(bs: &[u8]) -> String {
+    let mut res = "b\"".to_string();
+    for b in bs {
+        match b {
+            b'"' => res.push_str("\\\""),
+            b'\\' => res.push_str("\\\\"),
+            0x20..0x7f => res.push(*b as char),
+            _ => { let _ = write!(res, "\\x{:02x}", b); }
+        }
+    }
+    res.push('"');
+    res
+}
+
+
+#[cfg(test)]
+mod test_snippet {
+    use std::time::SystemTime;
+    use crate::data::http::parser::HttpParser;
+    use crate::data::http::HttpPacket;
+    use crate::data::snippet::{to_curl, to_python, to_raw};
+    use crate::data::{StreamDirection, StreamInfo};
+
+    fn packet(req: &[u8]) -> HttpPacket {
+        let request = HttpParser::new(StreamDirection::ClientToServer).push(req, SystemTime::now()).unwrap().remove(0);
+        let mut parser = HttpParser::new(StreamDirection::ServerToClient);
+        let response = parser.push(b"HTTP/1.1 204 No Content\r\n\r\n", SystemTime::now()).unwrap().remove(0);
+        HttpPacket::from_data(StreamInfo::new("1", "https", "a.com:443"), request, response)
+    }
+
+    #[test]
+    fn test_snippets() {
+        let packet = packet(b"POST /api?q=1 HTTP/1.1\r\nHost: a.com\r\nX-Name: it's\r\nContent-Length: 9\r\n\r\n{\"a\":\"'\"}");
+        assert_eq!(to_curl(&packet), "curl -X POST 'https://a.com/api?q=1' \\\n  -H 'X-Name: it'\\''s' \\\n  --data-binary '{\"a\":\"'\\''\"}'");
+        assert!(to_python(&packet).contains("data = '{\"a\":\"\\'\"}'\n"));
+        assert_eq!(to_raw(&packet), "POST /api?q=1 HTTP/1.1\r\nHost: a.com\r\nX-Name: it's\r\nContent-Length: 9\r\n\r\n{\"a\":\"'\"}");
+
+        let packet = self::packet(b"PUT /bin HTTP/1.1\r\nHost: a.com\r\nContent-Length: 3\r\n\r\n\x00\xff'");
+        assert!(to_curl(&packet).ends_with("--data-binary $'\\x00\\xff\\''"));
+        assert!(to_python(&packet).contains("data = b'\\x00\\xff\\''\n"));
+    }
+}
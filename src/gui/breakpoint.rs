@@ -0,0 +1,76 @@
+use egui::{Context, RichText, TextEdit, Window};
+use crate::gui::ProxyView;
+use crate::rule::breakpoint::{Paused, Resume};
+use crate::rule::Phase;
+
+//返回响应时编辑框里默认填写的内容
+const DEFAULT_RESPONSE: &str = "HTTP/1.1 200 OK\nContent-Type: text/plain; charset=utf-8";
+
+//等待处理的断点，头和body编辑的是文本
+pub struct PausedView {
+    paused: Paused,
+    head: String,
+    body: String,
+    //body不是UTF-8时文本有损，没有修改过就用原来的字节
+    origin_body: Option<String>,
+    //请求断点改成不发给服务器，直接返回编辑的响应
+    respond: bool,
+}
+
+impl PausedView {
+    pub fn new(paused: Paused) -> PausedView {
+        let body = String::from_utf8_lossy(paused.body()).to_string();
+        PausedView { head: paused.head().to_string(), origin_body: Some(body.clone()), body, paused, respond: false }
+    }
+
+    fn body(&self) -> Vec<u8> {
+        match &self.origin_body {
+            Some(body) if *body == self.body => self.paused.body().to_vec(),
+            _ => self.body.as_bytes().to_vec(),
+        }
+    }
+
+    fn to_resume(&self) -> Resume {
+        if self.respond {
+            Resume::Respond(self.head.clone(), self.body())
+        } else {
+            Resume::Continue(self.head.clone(), self.body())
+        }
+    }
+}
+
+impl ProxyView {
+    //一次只处理最早被拦住的报文，处理完再显示下一个
+    pub fn show_breakpoints(&mut self, ctx: &Context) {
+        let waiting = self.paused.len();
+        let Some(view) = self.paused.first_mut() else { return; };
+        let is_request = view.paused.phase() == Phase::Request && !view.respond;
+        let mut resume = None;
+        Window::new("断点").default_width(600.0).show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.strong(if view.paused.phase() == Phase::Request { "请求断点" } else { "响应断点" });
+                ui.label(format!("{} {}", view.paused.method(), view.paused.url()));
+            });
+            if waiting > 1 {
+                ui.label(RichText::new(format!("还有{}个报文等待处理", waiting - 1)).weak());
+            }
+            ui.label(if is_request { "请求头" } else { "响应头" });
+            ui.add(TextEdit::multiline(&mut view.head).code_editor().desired_rows(8).desired_width(f32::INFINITY));
+            ui.label(if is_request { "请求体" } else { "响应体" });
+            ui.add(TextEdit::multiline(&mut view.body).code_editor().desired_rows(8).desired_width(f32::INFINITY));
+            ui.horizontal(|ui| {
+                ui.button("继续").clicked().then(|| resume = Some(view.to_resume()));
+                ui.button("中止").clicked().then(|| resume = Some(Resume::Abort));
+                if is_request && ui.button("返回响应").on_hover_text("不发给服务器，编辑响应后直接回复客户端").clicked() {
+                    view.respond = true;
+                    view.head = DEFAULT_RESPONSE.to_string();
+                    view.body = String::new();
+                    view.origin_body = None;
+                }
+            });
+        });
+        if let Some(resume) = resume {
+            self.paused.remove(0).paused.resume(resume);
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use std::sync::mpsc;
+use egui::{Button, Color32, Context, RichText, TextEdit, Window};
+use tokio::runtime::Runtime;
+use crate::data::http::HttpPacket;
+use crate::error::ProxyResult;
+use crate::gui::ProxyView;
+use crate::proxy::replay;
+
+//编辑后重发的请求，body显示的是解压后的内容，所以打开时去掉压缩相关的字段
+pub struct Composer {
+    method: String,
+    url: String,
+    //每行一个字段
+    headers: String,
+    body: String,
+    //body不是UTF-8时文本有损，没有修改过就用原来的字节
+    origin_body: (String, Vec<u8>),
+    sending: bool,
+}
+
+const SKIP_HEADERS: [&str; 3] = ["Content-Length", "Transfer-Encoding", "Content-Encoding"];
+
+impl Composer {
+    pub fn from_packet(packet: &HttpPacket) -> Composer {
+        let headers = packet.request().header().keys().iter()
+            .filter(|(key, _)| !SKIP_HEADERS.iter().any(|h| h.eq_ignore_ascii_case(key)))
+            .map(|(key, value)| format!("{}: {}", key, value)).collect::<Vec<_>>().join("\n");
+        let body = packet.request().body().decoded();
+        let text = String::from_utf8_lossy(body).to_string();
+        Composer {
+            method: packet.method().to_string(),
+            url: packet.url(),
+            headers,
+            body: text.clone(),
+            origin_body: (text, body.to_vec()),
+            sending: false,
+        }
+    }
+
+    fn body(&self) -> Vec<u8> {
+        match &self.origin_body {
+            (text, body) if *text == self.body => body.clone(),
+            _ => self.body.as_bytes().to_vec(),
+        }
+    }
+
+    //请求行用完整的URL，Content-Length按body重新计算
+    fn to_raw(&self) -> Vec<u8> {
+        let mut head = format!("{} {} HTTP/1.1\r\n", self.method.trim(), self.url.trim());
+        for line in self.headers.lines().map(|line| line.trim()).filter(|line| !line.is_empty()) {
+            let key = line.split(":").next().unwrap_or("").trim();
+            if key.eq_ignore_ascii_case("Content-Length") || key.eq_ignore_ascii_case("Transfer-Encoding") { continue; }
+            head.push_str(line);
+            head.push_str("\r\n");
+        }
+        let body = self.body();
+        if !body.is_empty() || !matches!(self.method.trim(), "GET" | "HEAD") {
+            head.push_str(&format!("Content-Length: {}\r\n", body.len()));
+        }
+        head.push_str("\r\n");
+        let mut raw = head.into_bytes();
+        raw.extend(body);
+        raw
+    }
+}
+
+//原样重发时请求行换成完整的URL，其他部分保持抓到的样子
+pub fn absolute_raw(packet: &HttpPacket) -> Vec<u8> {
+    let raw = packet.request().raw();
+    let line_end = raw.windows(2).position(|w| w == b"\r\n").unwrap_or(raw.len());
+    let mut res = format!("{} {} HTTP/1.1", packet.method(), packet.url()).into_bytes();
+    res.extend(&raw[line_end..]);
+    res
+}
+
+//在代理的运行时里发送，结果通过通道交给界面
+pub fn send_request(runtime: &Runtime, sender: &mpsc::Sender<ProxyResult<HttpPacket>>, raw: Vec<u8>, ctx: &Context) {
+    let sender = sender.clone();
+    let ctx = ctx.clone();
+    runtime.spawn(async move {
+        let _ = sender.send(replay(raw).await);
+        ctx.request_repaint();
+    });
+}
+
+impl ProxyView {
+    //重发的结果作为新的请求加到列表最后
+    pub fn receive_replays(&mut self) {
+        while let Ok(res) = self.replay_receiver.try_recv() {
+            if let Some(composer) = &mut self.composer { composer.sending = false; }
+            let res = res.map(|packet| {
+                let message = format!("重发完成：{} {}", packet.method(), packet.url());
+                self.data.push(packet);
+                self.current_item = Some(self.data.len() - 1);
+                message
+            });
+            self.set_status_message(res, "重发请求失败");
+        }
+    }
+
+    pub fn show_composer(&mut self, ctx: &Context) {
+        let Some(mut composer) = self.composer.take() else { return; };
+        let mut open = true;
+        let mut send = false;
+        Window::new("编辑重发").open(&mut open).default_width(600.0).show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.add(TextEdit::singleline(&mut composer.method).desired_width(80.0));
+                ui.add(TextEdit::singleline(&mut composer.url).desired_width(f32::INFINITY));
+            });
+            ui.label("请求头");
+            ui.add(TextEdit::multiline(&mut composer.headers).code_editor().desired_rows(8).desired_width(f32::INFINITY));
+            ui.label("请求体");
+            ui.add(TextEdit::multiline(&mut composer.body).code_editor().desired_rows(8).desired_width(f32::INFINITY));
+            ui.horizontal(|ui| {
+                send = ui.add_enabled(!composer.sending, Button::new("发送")).clicked();
+                if composer.sending { ui.spinner(); }
+                if let Some((message, true)) = &self.status_message {
+                    ui.label(RichText::new(message).color(Color32::RED));
+                }
+            });
+        });
+        if send {
+            composer.sending = true;
+            self.status_message = None;
+            send_request(&self.runtime, &self.replay_sender, composer.to_raw(), ctx);
+        }
+        if open { self.composer = Some(composer); }
+    }
+}
+
+
+#[cfg(test)]
+mod test_composer {
+    use std::time::SystemTime;
+    use crate::data::http::parser::HttpParser;
+    use crate::data::http::HttpPacket;
+    use crate::data::{StreamDirection, StreamInfo};
+    use crate::gui::composer::{absolute_raw, Composer};
+
+    #[test]
+    fn test_to_raw() {
+        let req = b"POST /a HTTP/1.1\r\nHost: a.com\r\nContent-Length: 2\r\n\r\nhi";
+        let request = HttpParser::new(StreamDirection::ClientToServer).push(req, SystemTime::now()).unwrap().remove(0);
+        let response = HttpParser::new(StreamDirection::ServerToClient).push(b"HTTP/1.1 204 No Content\r\n\r\n", SystemTime::now()).unwrap().remove(0);
+        let packet = HttpPacket::from_data(StreamInfo::new("1", "https", "a.com:443"), request, response);
+        assert_eq!(absolute_raw(&packet), b"POST https://a.com/a HTTP/1.1\r\nHost: a.com\r\nContent-Length: 2\r\n\r\nhi");
+
+        let mut composer = Composer::from_packet(&packet);
+        composer.headers.push_str("\nX-Debug: 1");
+        composer.body = "hello".to_string();
+        assert_eq!(composer.to_raw(), b"POST https://a.com/a HTTP/1.1\r\nHost: a.com\r\nX-Debug: 1\r\nContent-Length: 5\r\n\r\nhello");
+
+        //没改过的二进制body原样发送
+        let req = b"POST /a HTTP/1.1\r\nHost: a.com\r\nContent-Length: 4\r\n\r\n\x89P\xffG";
+        let request = HttpParser::new(StreamDirection::ClientToServer).push(req, SystemTime::now()).unwrap().remove(0);
+        let response = HttpParser::new(StreamDirection::ServerToClient).push(b"HTTP/1.1 204 No Content\r\n\r\n", SystemTime::now()).unwrap().remove(0);
+        let packet = HttpPacket::from_data(StreamInfo::new("1", "https", "a.com:443"), request, response);
+        let composer = Composer::from_packet(&packet);
+        assert!(composer.to_raw().ends_with(b"Content-Length: 4\r\n\r\n\x89P\xffG"));
+    }
+}
@@ -0,0 +1,54 @@
+use egui::Ui;
+use egui_extras::{Column, TableBuilder};
+use crate::data::http::cookie::{parse_cookie, SetCookie};
+use crate::gui::{show_title, ProxyView};
+
+impl ProxyView {
+    pub fn show_cookies(&mut self, ui: &mut Ui) {
+        let Some(index) = self.current_item else { return; };
+        let datum = &self.data[index];
+        let cookies = parse_cookie(&datum.request().header().get_all("Cookie"));
+        let set_cookies = datum.response().header().get_all("Set-Cookie").into_iter().map(SetCookie::parse).collect::<Vec<_>>();
+        show_title(ui, "请求Cookie");
+        if cookies.is_empty() {
+            ui.label("没有Cookie");
+        } else {
+            let table = TableBuilder::new(ui).id_salt("request_cookies").striped(true).vscroll(false)
+                .column(Column::auto().at_least(100.0).resizable(true)).column(Column::remainder());
+            table.header(20.0, |mut header| {
+                for title in ["名称", "值"] {
+                    header.col(|ui| { ui.strong(title); });
+                }
+            }).body(|mut body| {
+                for (name, value) in &cookies {
+                    body.row(20.0, |mut row| {
+                        row.col(|ui| { ui.label(name); });
+                        row.col(|ui| { ui.label(value); });
+                    });
+                }
+            });
+        }
+        show_title(ui, "响应Set-Cookie");
+        if set_cookies.is_empty() {
+            ui.label("没有Set-Cookie");
+            return;
+        }
+        let table = TableBuilder::new(ui).id_salt("response_cookies").striped(true).vscroll(false)
+            .columns(Column::auto().at_least(50.0).resizable(true), 9);
+        table.header(20.0, |mut header| {
+            for title in ["名称", "值", "Domain", "Path", "Expires", "Max-Age", "Secure", "HttpOnly", "SameSite"] {
+                header.col(|ui| { ui.strong(title); });
+            }
+        }).body(|mut body| {
+            for cookie in &set_cookies {
+                body.row(20.0, |mut row| {
+                    let flag = |b: bool| if b { "✔" } else { "" };
+                    for text in [cookie.name.as_str(), &cookie.value, &cookie.domain, &cookie.path, &cookie.expires,
+                        &cookie.max_age, flag(cookie.secure), flag(cookie.http_only), &cookie.same_site] {
+                        row.col(|ui| { ui.label(text); });
+                    }
+                });
+            }
+        });
+    }
+}
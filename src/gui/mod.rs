@@ -1,18 +1,108 @@
 use crate::data::http::HttpPacket;
+use crate::gui::breakpoint::PausedView;
+use crate::gui::composer::{absolute_raw, send_request, Composer};
+use crate::gui::param::Params;
+use crate::gui::preview::Preview;
+use crate::gui::raw::RawView;
 use crate::data::ui::ProxyTab;
 use crate::data::FilterMode;
+use crate::data::search::SearchQuery;
+use crate::data::snippet;
+use crate::server::ProxyServer;
+use crate::rule::{Rules, SharedRules};
+use crate::error::ProxyResult;
 use eframe::emath::Align;
 use eframe::epaint::text::TextWrapMode;
 use eframe::{App, Frame};
-use egui::{include_image, Button, CentralPanel, Color32, Context, FontData, Id, Label, Layout, ScrollArea, Sense, Ui, UiBuilder, Visuals, Widget};
+use egui::{include_image, Button, CentralPanel, Color32, Context, FontData, Id, Label, Layout, RichText, ScrollArea, Sense, TextEdit, Ui, UiBuilder, Visuals, Widget};
+use log::error;
 use std::error::Error;
+use std::sync::{mpsc, OnceLock};
+use std::time::SystemTime;
+use time::macros::format_description;
+use time::{OffsetDateTime, UtcOffset};
+use tokio::runtime::Runtime;
+
+mod preview;
+mod param;
+mod cookie;
+mod raw;
+mod session;
+mod composer;
+mod rules;
+mod breakpoint;
+
+//代理监听的地址
+const PROXY_ADDR: &str = "0.0.0.0:7090";
+
+//右键菜单里复制代码的菜单项和生成代码的函数
+type Snippet = (&'static str, fn(&HttpPacket) -> String);
+
+//本地时区只能在单线程时获取，所以在启动界面之前先取好
+static LOCAL_OFFSET: OnceLock<UtcOffset> = OnceLock::new();
+
+pub fn init_local_offset() {
+    LOCAL_OFFSET.get_or_init(|| UtcOffset::current_local_offset().unwrap_or(UtcOffset::UTC));
+}
+
+fn format_time(time: SystemTime) -> String {
+    let offset = LOCAL_OFFSET.get().copied().unwrap_or(UtcOffset::UTC);
+    let time = OffsetDateTime::from(time).to_offset(offset);
+    time.format(format_description!("[hour]:[minute]:[second]")).unwrap_or_default()
+}
+
+fn format_size(size: usize) -> String {
+    match size {
+        0..1024 => format!("{} B", size),
+        1024..1048576 => format!("{:.1} Kb", size as f64 / 1024.0),
+        _ => format!("{:.1} Mb", size as f64 / 1048576.0),
+    }
+}
+
+//详情里每一部分的标题
+fn show_title(ui: &mut Ui, title: &str) {
+    ui.horizontal(|ui| {
+        ui.set_height(30.0);
+        let rect = ui.max_rect();
+        ui.painter().rect_filled(rect, 0.0, Color32::LIGHT_BLUE);
+        ui.label(title);
+    });
+}
 
 pub struct ProxyView {
     data: Vec<HttpPacket>,
     current_item: Option<usize>,
-    working: bool,
+    //界面持有一个运行时，代理服务跑在这里面
+    runtime: Runtime,
+    server: Option<ProxyServer>,
+    server_error: Option<String>,
     filter_mode: FilterMode,
     view_tab: ProxyTab,
+    //当前选中请求的预览，序号变了才重新生成
+    preview: Option<(usize, Preview)>,
+    //每次生成预览图片用不同的地址，避免加载器用到旧图片的缓存
+    preview_seq: usize,
+    params: Option<(usize, Params)>,
+    //原始报文用十六进制显示
+    raw_hex: bool,
+    raw_view: Option<RawView>,
+    search_text: String,
+    search: Option<SearchQuery>,
+    search_error: Option<String>,
+    //每个请求是否符合搜索条件，搜索条件变了才重新计算，新来的请求追加在后面
+    search_hits: Vec<bool>,
+    open_window: bool,
+    session_path: String,
+    //保存、打开、重发等操作的结果，第二个值表示是否出错
+    status_message: Option<(String, bool)>,
+    composer: Option<Composer>,
+    replay_sender: mpsc::Sender<ProxyResult<HttpPacket>>,
+    replay_receiver: mpsc::Receiver<ProxyResult<HttpPacket>>,
+    //和代理共享的规则，停止再启动代理时保留
+    rules: SharedRules,
+    rules_window: bool,
+    //被断点拦住、等待处理的报文
+    paused: Vec<PausedView>,
 }
 
 impl ProxyView {
@@ -29,12 +119,34 @@ impl ProxyView {
         ctx.egui_ctx.set_visuals(Visuals::light());
         //安装图片加载器
         egui_extras::install_image_loaders(&ctx.egui_ctx);
+        let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build()?;
+        let (replay_sender, replay_receiver) = mpsc::channel();
         Ok(Box::new(ProxyView {
-            data: vec![HttpPacket::new()],
+            data: vec![],
             current_item: None,
-            working: false,
+            runtime,
+            server: None,
+            server_error: None,
             filter_mode: FilterMode::None,
             view_tab: ProxyTab::Header,
+            preview: None,
+            preview_seq: 0,
+            params: None,
+            raw_hex: false,
+            raw_view: None,
+            search_text: String::new(),
+            search: None,
+            search_error: None,
+            search_hits: vec![],
+            open_window: false,
+            session_path: String::new(),
+            status_message: None,
+            composer: None,
+            replay_sender,
+            replay_receiver,
+            rules: Rules::shared(),
+            rules_window: false,
+            paused: vec![],
         }))
     }
 
@@ -42,19 +154,98 @@ impl ProxyView {
         ui.horizontal(|ui| {
             // ui.painter().rect_filled(ui.max_rect(), 0.0, Color32::BLUE);
             // ui.set_height(50.0);
-            let img = if self.working { include_image!("../../res/imgs/stop.png") } else { include_image!("../../res/imgs/start.png") };
-            let btn = Button::image_and_text(img, if self.working { "停止" } else { "启动" });
-            ui.add(btn).clicked().then(|| self.working = !self.working);
+            let working = self.server.is_some();
+            let img = if working { include_image!("../../res/imgs/stop.png") } else { include_image!("../../res/imgs/start.png") };
+            let btn = Button::image_and_text(img, if working { "停止" } else { "启动" });
+            ui.add(btn).clicked().then(|| self.toggle_server(ui.ctx()));
             let btn = Button::image_and_text(include_image!("../../res/imgs/save.png"), "保存");
-            ui.add(btn).clicked().then(|| {});
+            ui.add(btn).clicked().then(|| self.save_session());
+            ui.button("打开").clicked().then(|| self.open_window = true);
             let btn = Button::image_and_text(include_image!("../../res/imgs/export.png"), "导出");
-            ui.add(btn).clicked().then(|| {});
+            ui.add(btn).clicked().then(|| self.export_har());
+            ui.button("规则").clicked().then(|| self.rules_window = true);
             for mode in FilterMode::modes() {
                 ui.selectable_label(self.filter_mode == mode, mode.to_string()).clicked().then(|| self.filter_mode = mode);
             }
+            let search = TextEdit::singleline(&mut self.search_text).desired_width(300.0)
+                .hint_text("搜索，例如 host:a.com status:>=400 method:POST body~\"token\"");
+            ui.add(search).changed().then(|| self.update_search());
+            if let Some(e) = &self.search_error {
+                ui.label(RichText::new(e).color(Color32::RED));
+            }
+            if let Some(e) = &self.server_error {
+                ui.label(RichText::new(e).color(Color32::RED));
+            }
+            if let Some((message, is_error)) = &self.status_message {
+                ui.label(if *is_error { RichText::new(message).color(Color32::RED) } else { RichText::new(message) });
+            }
+        });
+    }
+
+    fn toggle_server(&mut self, ctx: &Context) {
+        match self.server.take() {
+            Some(mut server) => {
+                self.data.extend(server.packets());
+                server.stop();
+                //连接已经断开，等待中的断点不用再处理
+                self.paused.clear();
+            }
+            None => match ProxyServer::start(&self.runtime, PROXY_ADDR, ctx.clone(), self.rules.clone()) {
+                Ok(server) => {
+                    self.server = Some(server);
+                    self.server_error = None;
+                }
+                Err(e) => {
+                    error!("启动代理失败：{}", e.to_string());
+                    self.server_error = Some(format!("启动代理失败：{}", e.to_string()));
+                }
+            }
+        }
+    }
+
+    fn update_search(&mut self) {
+        self.search_hits.clear();
+        match SearchQuery::parse(&self.search_text) {
+            Ok(query) => {
+                self.search = if query.is_empty() { None } else { Some(query) };
+                self.search_error = None;
+            }
+            //输入到一半的正则可能是错的，保留上一次的搜索结果
+            Err(e) => self.search_error = Some(format!("搜索条件错误：{}", e.to_string())),
+        }
+    }
+
+    //操作成功时显示结果，失败时显示失败的原因
+    fn set_status_message(&mut self, res: ProxyResult<String>, failed: &str) {
+        self.status_message = Some(match res {
+            Ok(message) => (message, false),
+            Err(e) => {
+                error!("{}：{}", failed, e.to_string());
+                (format!("{}：{}", failed, e.to_string()), true)
+            }
         });
     }
 
+    //符合过滤和搜索条件的请求，编号还是原来的序号
+    fn visible_items(&mut self) -> Vec<usize> {
+        if let Some(search) = &self.search {
+            for datum in &self.data[self.search_hits.len()..] {
+                self.search_hits.push(search.matches(datum));
+            }
+        }
+        self.data.iter().enumerate()
+            .filter(|(index, datum)| self.filter_mode.matches(datum) && (self.search.is_none() || self.search_hits[*index]))
+            .map(|(index, _)| index).collect()
+    }
+
+    //把代理抓到的数据放进列表
+    fn receive_packets(&mut self) {
+        if let Some(server) = &mut self.server {
+            self.data.extend(server.packets());
+            self.paused.extend(server.pauses().into_iter().map(PausedView::new));
+        }
+    }
+
     fn show_item(&mut self, index: usize, ui: &mut Ui) {
         let item_rect = ui.max_rect();
         let mut item_layout_rect = ui.max_rect();
@@ -67,10 +258,8 @@ impl ProxyView {
             let datum = &self.data[index];
             ui.vertical(|ui| {
                 //这里的样式我们后面再更换
-                if let Some(current_item) = self.current_item {
-                    if current_item == index {
-                        ui.painter().rect_filled(item_rect, 0.2, Color32::LIGHT_BLUE);
-                    }
+                if self.current_item == Some(index) {
+                    ui.painter().rect_filled(item_rect, 0.2, Color32::LIGHT_BLUE);
                 }
                 let resp = ui.interact(item_rect, Id::from(format!("item_{}", index)), Sense::click_and_drag());
                 if resp.hovered() {
@@ -79,15 +268,53 @@ impl ProxyView {
                 if resp.clicked() {
                     self.current_item = Some(index);
                 }
+                //右键菜单，把请求复制成各种工具的代码
+                resp.context_menu(|ui| {
+                    let snippets: [Snippet; 5] = [("复制为curl", snippet::to_curl), ("复制为wget", snippet::to_wget),
+                        ("复制为Python requests", snippet::to_python), ("复制为Rust reqwest", snippet::to_reqwest), ("复制为HTTP原始报文", snippet::to_raw)];
+                    for (title, snippet) in snippets {
+                        if ui.button(title).clicked() {
+                            ui.ctx().copy_text(snippet(datum));
+                            ui.close_menu();
+                        }
+                    }
+                    ui.separator();
+                    if ui.button("重发").clicked() {
+                        send_request(&self.runtime, &self.replay_sender, absolute_raw(datum), ui.ctx());
+                        ui.close_menu();
+                    }
+                    if ui.button("编辑后重发").clicked() {
+                        self.composer = Some(Composer::from_packet(datum));
+                        ui.close_menu();
+                    }
+                });
                 //保证不自动换行
-                let url = Label::new("https://docs.rs/eframe/latest/eframe/").wrap_mode(TextWrapMode::Extend).truncate();
+                let url = Label::new(datum.url()).wrap_mode(TextWrapMode::Extend).truncate();
                 ui.add(url);
                 ui.horizontal(|ui| {
                     ui.label(index.to_string());
-                    ui.label(200.to_string());
-                    ui.label("文档");
-                    ui.label("08:00");
-                    ui.label("1.6 Kb");
+                    ui.label(datum.method());
+                    //出错的请求用红色标出来
+                    //没有收到响应时不显示状态码
+                    let status = if datum.response().raw().is_empty() {
+                        RichText::new("-")
+                    } else {
+                        RichText::new(datum.status().code().to_string())
+                    };
+                    ui.label(if datum.status().is_error() { status.color(Color32::RED) } else { status });
+                    ui.label(datum.content_type().split(";").next().unwrap_or(""));
+                    ui.label(format_time(datum.time()));
+                    ui.label(format_size(datum.size()));
+                    if datum.stream().blocked() {
+                        ui.label(RichText::new("已拦截").color(Color32::from_rgb(160, 0, 160)));
+                    }
+                    if datum.stream().tunneled() {
+                        ui.label(RichText::new("未解密").color(Color32::GRAY));
+                    }
+                    //被规则或者断点修改过的请求
+                    if datum.stream().modified() {
+                        ui.label(RichText::new("已修改").color(Color32::from_rgb(200, 120, 0)));
+                    }
                 });
             });
         });
@@ -104,8 +331,9 @@ impl ProxyView {
         ui.vertical(|ui| {
             ui.set_width(400.0);
             let area = ScrollArea::vertical().auto_shrink([false; 2]).stick_to_bottom(true);
-            area.show_rows(ui, 50.0, self.data.len(), |ui, rows| {
-                for row in rows { self.show_item(row, ui); }
+            let visible = self.visible_items();
+            area.show_rows(ui, 50.0, visible.len(), |ui, rows| {
+                for row in rows { self.show_item(visible[row], ui); }
             });
         });
     }
@@ -132,33 +360,35 @@ impl ProxyView {
     }
 
     fn show_headers(&mut self, ui: &mut Ui) {
-        ui.horizontal(|ui| {
-            ui.set_height(30.0);
-            let rect = ui.max_rect();
-            ui.painter().rect_filled(rect, 0.0, Color32::LIGHT_BLUE);
-            ui.label("总揽");
-        });
-        self.show_header_item(ui, "请求URL", r#"data:image/svg+xml,<svg width="18" height="18" viewBox="0 0 12 12" %09enable-background="new 0 0 12 12" xmlns="http://www.w3.org/2000/svg" fill="none">%09<circle r="5.25" cx="6" cy="6" stroke-width="1.25" stroke="black"/>%09<text x="6" y="7" style="font:8px sans-serif;font-weight:1000" text-anchor="middle" %09%09dominant-baseline="middle" fill="black">?%3C/text%3E%3C/svg%3Edfjkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkkk"#);
-        self.show_header_item(ui, "请求方法", "POST");
-        self.show_header_item(ui, "状态码", "200");
-        self.show_header_item(ui, "目标地址", "127.0.0.1:443");
-        ui.horizontal(|ui| {
-            ui.set_height(30.0);
-            let rect = ui.max_rect();
-            ui.painter().rect_filled(rect, 0.0, Color32::LIGHT_BLUE);
-            ui.label("请求标头");
-        });
+        show_title(ui, "总揽");
         let datum = &self.data[self.current_item.unwrap_or(0)];
-        for (key, value) in datum.request().header().keys() {
+        self.show_header_item(ui, "请求URL", datum.url());
+        self.show_header_item(ui, "请求方法", datum.method());
+        self.show_header_item(ui, "状态码", datum.status().to_string());
+        self.show_header_item(ui, "目标地址", datum.stream().target());
+        if let Some(remap) = datum.stream().remap() {
+            self.show_header_item(ui, "映射前URL", remap);
+        }
+        if !datum.stream().rewrites().is_empty() {
+            self.show_header_item(ui, "修改", datum.stream().rewrites().join("；"));
+        }
+        if datum.stream().tunneled() {
+            self.show_header_item(ui, "HTTPS", "未解密，原样转发");
+        }
+        if let Some(tls) = datum.stream().tls() {
+            self.show_header_item(ui, "SNI", tls.sni());
+            self.show_header_item(ui, "TLS版本", tls.version());
+            self.show_header_item(ui, "加密套件", tls.cipher());
+            self.show_header_item(ui, "ALPN", tls.alpn());
+        }
+        self.show_header_item(ui, "请求时间", format_time(datum.time()));
+        self.show_header_item(ui, "总大小", format_size(datum.size()));
+        show_title(ui, "请求标头");
+        for (key, value) in datum.request().header().keys().iter() {
             self.show_header_item(ui, key, value);
         }
-        ui.horizontal(|ui| {
-            ui.set_height(30.0);
-            let rect = ui.max_rect();
-            ui.painter().rect_filled(rect, 0.0, Color32::LIGHT_BLUE);
-            ui.label("响应标头");
-        });
-        for (key, value) in datum.response().header().keys() {
+        show_title(ui, "响应标头");
+        for (key, value) in datum.response().header().keys().iter() {
             self.show_header_item(ui, key, value);
         }
     }
@@ -184,11 +414,10 @@ impl ProxyView {
             area.show(ui, |ui| {
                 match self.view_tab {
                     ProxyTab::Header => { ui.vertical(|ui| self.show_headers(ui)); }
-                    ProxyTab::PreView => {}
-                    ProxyTab::Param => {}
-                    ProxyTab::Cookie => {}
-                    ProxyTab::ReqRaw => {}
-                    ProxyTab::RespRaw => {}
+                    ProxyTab::PreView => { ui.vertical(|ui| self.show_preview(ui)); }
+                    ProxyTab::Param => { ui.vertical(|ui| self.show_params(ui)); }
+                    ProxyTab::Cookie => { ui.vertical(|ui| self.show_cookies(ui)); }
+                    ProxyTab::ReqRaw | ProxyTab::RespRaw => { ui.vertical(|ui| self.show_raw(ui)); }
                 }
             });
         });
@@ -197,6 +426,12 @@ impl ProxyView {
 
 impl App for ProxyView {
     fn update(&mut self, ctx: &Context, _frame: &mut Frame) {
+        self.receive_packets();
+        self.receive_replays();
+        self.show_open_window(ctx);
+        self.show_composer(ctx);
+        self.show_rules_window(ctx);
+        self.show_breakpoints(ctx);
         CentralPanel::default().show(ctx, |ui| {
             self.show_root_top(ui);
             let app_height = ui.max_rect().height();
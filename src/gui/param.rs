@@ -0,0 +1,111 @@
+use egui::{Color32, RichText, Ui};
+use serde_json::Value;
+use crate::data::http::HttpPacket;
+use crate::data::http::param::{parse_multipart, parse_query, query_of, MultipartPart};
+use crate::gui::preview::{hex_dump, show_json, show_monospace, truncate, MAX_PREVIEW_TEXT};
+use crate::gui::{format_size, show_title, ProxyView};
+
+//multipart里面的文本超过这个长度就不显示内容了
+const MAX_PART_TEXT: usize = 4096;
+
+//解析好的请求参数，切换选中的请求时才重新解析
+pub struct Params {
+    query: Vec<(String, String)>,
+    body: ParamBody,
+}
+
+enum ParamBody {
+    Empty,
+    Form(Vec<(String, String)>),
+    Multipart(Vec<MultipartPart>),
+    Json(Value),
+    //其他类型的body，或者按Content-Type解析失败时，原样显示
+    Other(String, Option<String>),
+}
+
+impl Params {
+    pub fn new(packet: &HttpPacket) -> Params {
+        let query = parse_query(query_of(&packet.url()));
+        let request = packet.request();
+        let body = request.body().decoded();
+        let content_type = request.header().get("Content-Type").unwrap_or("");
+        let mime = content_type.split(";").next().unwrap_or("").trim().to_lowercase();
+        let body = if body.is_empty() {
+            ParamBody::Empty
+        } else if mime == "application/x-www-form-urlencoded" {
+            ParamBody::Form(parse_query(&String::from_utf8_lossy(body)))
+        } else if mime == "multipart/form-data" {
+            match parse_multipart(content_type, body) {
+                Ok(parts) => ParamBody::Multipart(parts),
+                Err(e) => ParamBody::Other(body_text(body), Some(e.to_string())),
+            }
+        } else if mime.contains("json") && body.len() <= MAX_PREVIEW_TEXT {
+            match serde_json::from_slice(body) {
+                Ok(value) => ParamBody::Json(value),
+                Err(e) => ParamBody::Other(body_text(body), Some(format!("JSON解析失败：{}", e))),
+            }
+        } else {
+            ParamBody::Other(body_text(body), None)
+        };
+        Params { query, body }
+    }
+}
+
+fn body_text(body: &[u8]) -> String {
+    match std::str::from_utf8(body) {
+        Ok(text) => truncate(text, MAX_PREVIEW_TEXT),
+        Err(_) => hex_dump(body),
+    }
+}
+
+impl ProxyView {
+    pub fn show_params(&mut self, ui: &mut Ui) {
+        let Some(index) = self.current_item else { return; };
+        if !matches!(&self.params, Some((i, _)) if *i == index) {
+            self.params = Some((index, Params::new(&self.data[index])));
+        }
+        let Some((_, params)) = &self.params else { return; };
+        if !params.query.is_empty() {
+            show_title(ui, "查询参数");
+            for (key, value) in &params.query {
+                self.show_header_item(ui, key, value);
+            }
+        }
+        match &params.body {
+            ParamBody::Empty => if params.query.is_empty() { ui.label("没有请求参数"); },
+            ParamBody::Form(fields) => {
+                show_title(ui, "表单参数");
+                for (key, value) in fields {
+                    self.show_header_item(ui, key, value);
+                }
+            }
+            ParamBody::Multipart(parts) => {
+                show_title(ui, "表单参数");
+                for part in parts {
+                    let title = match part.filename() {
+                        Some(filename) => format!("{}（文件：{}）", part.name(), filename),
+                        None => part.name(),
+                    };
+                    ui.label(RichText::new(title).strong());
+                    for (key, value) in part.headers().iter() {
+                        self.show_header_item(ui, key, value);
+                    }
+                    match std::str::from_utf8(part.body()) {
+                        Ok(text) if text.len() <= MAX_PART_TEXT => show_monospace(ui, text),
+                        _ => { ui.label(format!("{}的数据", format_size(part.body().len()))); }
+                    }
+                    ui.separator();
+                }
+            }
+            ParamBody::Json(value) => {
+                show_title(ui, "请求体");
+                show_json(ui, "", value, "param_json".to_string(), 0);
+            }
+            ParamBody::Other(text, error) => {
+                show_title(ui, "请求体");
+                if let Some(e) = error { ui.label(RichText::new(e).color(Color32::RED)); }
+                show_monospace(ui, text);
+            }
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use std::fmt::Write;
+use std::sync::Arc;
+use eframe::epaint::text::TextWrapMode;
+use egui::{CollapsingHeader, Color32, Image, Label, RichText, Ui};
+use serde_json::Value;
+use crate::gui::ProxyView;
+
+//文本太长时egui渲染会很卡，预览只显示前面一部分
+pub const MAX_PREVIEW_TEXT: usize = 256 * 1024;
+const MAX_PREVIEW_HEX: usize = 64 * 1024;
+//缩进的最大层数，嵌套很深时每行前面的空格会让格式化的结果成倍增长
+const MAX_INDENT: usize = 32;
+//HTML里没有结束标签的元素
+const VOID_ELEMENTS: [&str; 14] = ["area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source", "track", "wbr"];
+
+//预览的内容，切换选中的请求时才重新生成，避免每一帧都去解析
+pub enum Preview {
+    Empty,
+    Json(Value),
+    Markup(String),
+    Image(String, Arc<[u8]>),
+    Text(String),
+    Hex(String),
+}
+
+impl Preview {
+    //按Content-Type选择预览方式，没有Content-Type时根据内容猜一下
+    pub fn new(content_type: &str, body: &[u8], uri: String) -> Preview {
+        if body.is_empty() { return Preview::Empty; }
+        let mime = content_type.split(";").next().unwrap_or("").trim().to_lowercase();
+        let unknown = mime.is_empty() || mime == "application/octet-stream";
+        if (mime.starts_with("image/") || unknown) && image::guess_format(body).is_ok() {
+            return Preview::Image(uri, Arc::from(body));
+        }
+        let trimmed = body.trim_ascii_start();
+        //太大的JSON展开成树也很卡，按文本只显示前面一部分
+        let json = mime.contains("json") || (unknown && (trimmed.starts_with(b"{") || trimmed.starts_with(b"[")));
+        if json && body.len() <= MAX_PREVIEW_TEXT && let Ok(value) = serde_json::from_slice(body) {
+            return Preview::Json(value);
+        }
+        if mime.contains("xml") || mime.contains("html") || (unknown && trimmed.starts_with(b"<")) {
+            return Preview::Markup(pretty_markup(&String::from_utf8_lossy(body)));
+        }
+        match std::str::from_utf8(body) {
+            Ok(text) => Preview::Text(truncate(text, MAX_PREVIEW_TEXT)),
+            Err(_) => Preview::Hex(hex_dump(body)),
+        }
+    }
+}
+
+impl ProxyView {
+    pub fn show_preview(&mut self, ui: &mut Ui) {
+        let Some(index) = self.current_item else { return; };
+        if !matches!(&self.preview, Some((i, _)) if *i == index) {
+            //旧的图片从加载器的缓存里清掉
+            if let Some((_, Preview::Image(uri, _))) = &self.preview { ui.ctx().forget_image(uri); }
+            self.preview_seq += 1;
+            let response = self.data[index].response();
+            let uri = format!("bytes://preview/{}", self.preview_seq);
+            let preview = Preview::new(response.header().get("Content-Type").unwrap_or(""), response.body().decoded(), uri);
+            self.preview = Some((index, preview));
+        }
+        if let Some(e) = self.data[index].response().body().decode_error() {
+            ui.label(RichText::new(e).color(Color32::RED));
+        }
+        let Some((_, preview)) = &self.preview else { return; };
+        match preview {
+            Preview::Empty => { ui.label("没有响应内容"); }
+            Preview::Json(value) => show_json(ui, "", value, "json".to_string(), 0),
+            Preview::Image(uri, bytes) => {
+                let image = Image::from_bytes(uri.clone(), bytes.clone()).max_width(ui.available_width()).fit_to_original_size(1.0);
+                ui.add(image);
+            }
+            Preview::Markup(text) | Preview::Text(text) | Preview::Hex(text) => show_monospace(ui, text),
+        }
+    }
+}
+
+pub fn show_monospace(ui: &mut Ui, text: &str) {
+    ui.add(Label::new(RichText::new(text).monospace()).wrap_mode(TextWrapMode::Wrap));
+}
+
+//JSON按树形展示，对象和数组可以折叠，前两层默认展开
+pub fn show_json(ui: &mut Ui, key: &str, value: &Value, path: String, depth: usize) {
+    let prefix = if key.is_empty() { "".to_string() } else { format!("{}: ", key) };
+    match value {
+        Value::Object(map) => {
+            CollapsingHeader::new(format!("{}{{{}}}", prefix, map.len())).id_salt(&path).default_open(depth < 2).show(ui, |ui| {
+                for (k, v) in map {
+                    show_json(ui, k, v, format!("{}/{}", path, k), depth + 1);
+                }
+            });
+        }
+        Value::Array(items) => {
+            CollapsingHeader::new(format!("{}[{}]", prefix, items.len())).id_salt(&path).default_open(depth < 2).show(ui, |ui| {
+                for (i, v) in items.iter().enumerate() {
+                    show_json(ui, &i.to_string(), v, format!("{}/{}", path, i), depth + 1);
+                }
+            });
+        }
+        _ => {
+            let (text, color) = match value {
+                Value::String(s) => (format!("{:?}", s), Color32::DARK_GREEN),
+                Value::Number(n) => (n.to_string(), Color32::BLUE),
+                Value::Bool(b) => (b.to_string(), Color32::from_rgb(160, 32, 240)),
+                _ => ("null".to_string(), Color32::GRAY),
+            };
+            ui.horizontal_wrapped(|ui| {
+                ui.spacing_mut().item_spacing.x = 0.0;
+                ui.label(RichText::new(prefix).monospace());
+                ui.label(RichText::new(text).monospace().color(color));
+            });
+        }
+    }
+}
+
+pub fn truncate(text: &str, max: usize) -> String {
+    let shown = prefix(text, max);
+    if shown.len() == text.len() { return text.to_string(); }
+    format!("{}\n{}", shown, truncated_note(shown.len()))
+}
+
+//不超过max字节的最长前缀，不会切断字符
+fn prefix(text: &str, max: usize) -> &str {
+    if text.len() <= max { return text; }
+    let mut end = max;
+    while !text.is_char_boundary(end) { end -= 1; }
+    &text[..end]
+}
+
+fn truncated_note(shown: usize) -> String {
+    format!("……（只显示前{}字节）", shown)
+}
+
+//十六进制视图，每行16个字节，右边是可以显示的ASCII字符，太长时只显示前面一部分
+pub fn hex_dump(bs: &[u8]) -> String {
+    let mut res = String::new();
+    for (i, line) in bs[..bs.len().min(MAX_PREVIEW_HEX)].chunks(16).enumerate() {
+        let _ = write!(res, "{:08x}  ", i * 16);
+        for j in 0..16 {
+            match line.get(j) {
+                Some(b) => { let _ = write!(res, "{:02x} ", b); }
+                None => res.push_str("   "),
+            }
+            if j == 7 { res.push(' '); }
+        }
+        res.push_str(" |");
+        res.extend(line.iter().map(|b| if (0x20..0x7f).contains(b) { *b as char } else { '.' }));
+        res.push_str("|\n");
+    }
+    if bs.len() > MAX_PREVIEW_HEX { res.push_str(&truncated_note(MAX_PREVIEW_HEX)); }
+    res
+}
+
+//把XML和HTML按标签的层级缩进，script和style里面的内容原样保留，太长时只格式化前面一部分
+pub fn pretty_markup(text: &str) -> String {
+    let mut res = String::new();
+    let mut depth = 0usize;
+    let shown = prefix(text, MAX_PREVIEW_TEXT);
+    let mut rest = shown;
+    let mut push_line = |depth: usize, line: &str| {
+        res.push_str(&"  ".repeat(depth.min(MAX_INDENT)));
+        res.push_str(line);
+        res.push('\n');
+    };
+    while !rest.is_empty() {
+        let end = if rest.starts_with("<!--") {
+            let end = rest.find("-->").map(|p| p + 3).unwrap_or(rest.len());
+            push_line(depth, &rest[..end]);
+            end
+        } else if rest.starts_with("<![CDATA[") {
+            let end = rest.find("]]>").map(|p| p + 3).unwrap_or(rest.len());
+            push_line(depth, &rest[..end]);
+            end
+        } else if rest.starts_with("<") {
+            let mut end = rest.find(">").map(|p| p + 1).unwrap_or(rest.len());
+            let tag = &rest[..end];
+            let name = tag.trim_start_matches(['<', '/']).split(|c: char| !(c.is_alphanumeric() || c == '-' || c == ':'))
+                .next().unwrap_or("").to_lowercase();
+            if tag.starts_with("</") {
+                depth = depth.saturating_sub(1);
+                push_line(depth, tag);
+            } else {
+                push_line(depth, tag);
+                let opens = !tag.ends_with("/>") && !tag.starts_with("<?") && !tag.starts_with("<!") && !VOID_ELEMENTS.contains(&name.as_str());
+                if opens { depth += 1; }
+                if opens && (name == "script" || name == "style") {
+                    //这两个标签里面可能有<，不能当作标签解析；转小写会改变非ASCII字符的字节长度，所以直接比较原始字节
+                    let close = format!("</{}", name);
+                    let content_end = rest.as_bytes()[end..].windows(close.len())
+                        .position(|w| w.eq_ignore_ascii_case(close.as_bytes())).map(|p| p + end).unwrap_or(rest.len());
+                    for line in rest[end..content_end].lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+                        push_line(depth, line);
+                    }
+                    end = content_end;
+                }
+            }
+            end
+        } else {
+            let end = rest.find("<").unwrap_or(rest.len());
+            for line in rest[..end].lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+                push_line(depth, line);
+            }
+            end
+        };
+        rest = &rest[end..];
+    }
+    if shown.len() < text.len() { res.push_str(&truncated_note(shown.len())); }
+    res
+}
+
+
+#[cfg(test)]
+mod test_preview {
+    use crate::gui::preview::{hex_dump, pretty_markup, MAX_PREVIEW_HEX, MAX_PREVIEW_TEXT};
+
+    #[test]
+    fn test_pretty_markup() {
+        let html = "<html><head><meta charset=\"utf-8\"><script>if (a<b) {}</script></head><body><p>hi<br/></p></body></html>";
+        let expected = "<html>\n  <head>\n    <meta charset=\"utf-8\">\n    <script>\n      if (a<b) {}\n    </script>\n  </head>\n  <body>\n    <p>\n      hi\n      <br/>\n    </p>\n  </body>\n</html>\n";
+        assert_eq!(pretty_markup(html), expected);
+        let html = "<SCRIPT>var s = \"İİİİİİİİİİ\";</Script><p>İ</p>";
+        let expected = "<SCRIPT>\n  var s = \"İİİİİİİİİİ\";\n</Script>\n<p>\n  İ\n</p>\n";
+        assert_eq!(pretty_markup(html), expected);
+        //太长时只格式化前面一部分，嵌套很深也不会让缩进无限增长
+        let pretty = pretty_markup(&"<p>".repeat(MAX_PREVIEW_TEXT));
+        assert!(pretty.len() < MAX_PREVIEW_TEXT * 30);
+        assert!(pretty.ends_with(&format!("……（只显示前{}字节）", MAX_PREVIEW_TEXT)));
+    }
+
+    #[test]
+    fn test_hex_dump() {
+        let dump = hex_dump(b"HTTP/1.1 200 OK\r\n\x00");
+        assert_eq!(dump, "00000000  48 54 54 50 2f 31 2e 31  20 32 30 30 20 4f 4b 0d  |HTTP/1.1 200 OK.|\n\
+                          00000010  0a 00                                             |..|\n");
+        //超出的部分不显示，最后说明一下
+        let dump = hex_dump(&[0; MAX_PREVIEW_HEX + 1]);
+        assert_eq!(dump.lines().count(), MAX_PREVIEW_HEX / 16 + 1);
+        assert!(dump.ends_with(&format!("……（只显示前{}字节）", MAX_PREVIEW_HEX)));
+    }
+}
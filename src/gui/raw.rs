@@ -0,0 +1,36 @@
+use egui::Ui;
+use crate::data::ui::ProxyTab;
+use crate::gui::preview::{hex_dump, show_monospace, truncate, MAX_PREVIEW_TEXT};
+use crate::gui::{format_size, ProxyView};
+
+//原始报文的显示内容，选中的请求、标签页或者显示方式变了才重新生成
+pub struct RawView {
+    index: usize,
+    tab: ProxyTab,
+    hex: bool,
+    text: String,
+}
+
+impl ProxyView {
+    //显示网络上传输的原始字节，可以在文本和十六进制之间切换
+    pub fn show_raw(&mut self, ui: &mut Ui) {
+        let Some(index) = self.current_item else { return; };
+        let datum = &self.data[index];
+        let data = if self.view_tab == ProxyTab::ReqRaw { datum.request() } else { datum.response() };
+        ui.horizontal(|ui| {
+            ui.selectable_label(!self.raw_hex, "文本").clicked().then(|| self.raw_hex = false);
+            ui.selectable_label(self.raw_hex, "十六进制").clicked().then(|| self.raw_hex = true);
+            ui.label(format_size(data.raw().len()));
+        });
+        if !matches!(&self.raw_view, Some(v) if v.index == index && v.tab == self.view_tab && v.hex == self.raw_hex) {
+            let raw = data.raw();
+            let text = if self.raw_hex {
+                hex_dump(raw)
+            } else {
+                truncate(&String::from_utf8_lossy(raw), MAX_PREVIEW_TEXT)
+            };
+            self.raw_view = Some(RawView { index, tab: self.view_tab.clone(), hex: self.raw_hex, text });
+        }
+        if let Some(view) = &self.raw_view { show_monospace(ui, &view.text); }
+    }
+}
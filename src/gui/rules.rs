@@ -0,0 +1,170 @@
+use egui::cache::{ComputerMut, FrameCache};
+use egui::{Color32, ComboBox, Context, DragValue, Grid, RichText, TextEdit, Ui, Window};
+use regex::bytes::Regex;
+use regex::RegexBuilder;
+use crate::gui::{show_title, ProxyView};
+use crate::rule::block::{Block, BlockAction};
+use crate::rule::breakpoint::Breakpoint;
+use crate::rule::map_local::MapLocal;
+use crate::rule::map_remote::MapRemote;
+use crate::rule::rewrite::{Rewrite, RewriteAction};
+use crate::rule::ssl::SslRule;
+use crate::rule::throttle::{Profile, Throttle};
+use crate::rule::{read_rules, write_rules, Phase, RuleMatcher};
+
+//主机、路径和方法三个匹配条件
+fn show_matcher(ui: &mut Ui, matcher: &mut RuleMatcher) {
+    ui.add(TextEdit::singleline(&mut matcher.host).hint_text("*.example.com").desired_width(160.0));
+    ui.add(TextEdit::singleline(&mut matcher.path).hint_text("/api/*").desired_width(160.0));
+    ui.add(TextEdit::singleline(&mut matcher.method).hint_text("全部").desired_width(60.0));
+}
+
+//一类规则的表格，每行最后是删除按钮，表格下面是添加按钮
+fn show_rule_grid<T>(ui: &mut Ui, id: &str, titles: &[&str], rules: &mut Vec<T>, add: &str, new: fn() -> T,
+                     mut show_row: impl FnMut(&mut Ui, usize, &mut T)) {
+    let mut removed = None;
+    Grid::new(id).striped(true).show(ui, |ui| {
+        for title in titles {
+            ui.strong(*title);
+        }
+        ui.strong("");
+        ui.end_row();
+        for (i, rule) in rules.iter_mut().enumerate() {
+            show_row(ui, i, rule);
+            ui.button("删除").clicked().then(|| removed = Some(i));
+            ui.end_row();
+        }
+    });
+    if let Some(i) = removed { rules.remove(i); }
+    ui.button(add).clicked().then(|| rules.push(new()));
+}
+
+//检查正则能不能编译，第二个值表示按字节匹配，结果按帧缓存，内容没变就不用每帧重新编译
+#[derive(Default)]
+struct RegexCheck;
+
+impl ComputerMut<(&str, bool), bool> for RegexCheck {
+    fn compute(&mut self, (pattern, bytes): (&str, bool)) -> bool {
+        if bytes { Regex::new(pattern).is_ok() } else { RegexBuilder::new(pattern).build().is_ok() }
+    }
+}
+
+fn show_regex_error(ui: &mut Ui, pattern: &str, bytes: bool) {
+    let valid = ui.memory_mut(|mem| mem.caches.cache::<FrameCache<bool, RegexCheck>>().get((pattern, bytes)));
+    if !valid {
+        ui.label(RichText::new("正则错误").color(Color32::RED));
+    }
+}
+
+impl ProxyView {
+    //编辑的是规则的副本，有改动时才写回共享的配置，代理处理下一个请求时就会用到
+    pub fn show_rules_window(&mut self, ctx: &Context) {
+        if !self.rules_window { return; }
+        let mut open = true;
+        let origin = read_rules(&self.rules).clone();
+        let mut rules = origin.clone();
+        Window::new("规则").open(&mut open).default_width(600.0).show(ctx, |ui| {
+            show_title(ui, "断点");
+            show_rule_grid(ui, "breakpoint_rules", &["启用", "主机", "路径", "方法", "请求", "响应"],
+                           &mut rules.breakpoints, "添加断点", Breakpoint::new, |ui, _, breakpoint| {
+                ui.checkbox(&mut breakpoint.enabled, "");
+                show_matcher(ui, &mut breakpoint.matcher);
+                ui.checkbox(&mut breakpoint.request, "");
+                ui.checkbox(&mut breakpoint.response, "");
+            });
+            show_title(ui, "本地映射");
+            show_rule_grid(ui, "map_local_rules", &["启用", "主机", "路径", "方法", "本地文件或目录"],
+                           &mut rules.map_locals, "添加本地映射", MapLocal::new, |ui, _, map_local| {
+                ui.checkbox(&mut map_local.enabled, "");
+                show_matcher(ui, &mut map_local.matcher);
+                ui.add(TextEdit::singleline(&mut map_local.local).hint_text("/home/me/dist").desired_width(200.0));
+            });
+            show_title(ui, "远程映射");
+            show_rule_grid(ui, "map_remote_rules", &["启用", "主机", "路径", "方法", "新协议", "新主机", "新端口", "新路径", "保留Host"],
+                           &mut rules.map_remotes, "添加远程映射", MapRemote::new, |ui, _, map_remote| {
+                ui.checkbox(&mut map_remote.enabled, "");
+                show_matcher(ui, &mut map_remote.matcher);
+                ui.add(TextEdit::singleline(&mut map_remote.scheme).hint_text("不变").desired_width(50.0));
+                ui.add(TextEdit::singleline(&mut map_remote.host).hint_text("不变").desired_width(140.0));
+                ui.add(TextEdit::singleline(&mut map_remote.port).hint_text("不变").desired_width(50.0));
+                ui.add(TextEdit::singleline(&mut map_remote.path).hint_text("不变").desired_width(120.0));
+                ui.checkbox(&mut map_remote.keep_host, "");
+            });
+            show_title(ui, "改写");
+            show_rule_grid(ui, "rewrite_rules", &["启用", "主机", "路径", "方法", "作用于", "操作", "字段名或正则", "值"],
+                           &mut rules.rewrites, "添加改写", Rewrite::new, |ui, i, rewrite| {
+                ui.checkbox(&mut rewrite.enabled, "");
+                show_matcher(ui, &mut rewrite.matcher);
+                ComboBox::from_id_salt(("rewrite_phase", i)).width(50.0)
+                    .selected_text(if rewrite.phase == Phase::Request { "请求" } else { "响应" }).show_ui(ui, |ui| {
+                    ui.selectable_value(&mut rewrite.phase, Phase::Request, "请求");
+                    ui.selectable_value(&mut rewrite.phase, Phase::Response, "响应");
+                });
+                ComboBox::from_id_salt(("rewrite_action", i)).width(80.0).selected_text(rewrite.action.to_string()).show_ui(ui, |ui| {
+                    for action in RewriteAction::actions() {
+                        ui.selectable_value(&mut rewrite.action, action, action.to_string());
+                    }
+                });
+                ui.horizontal(|ui| {
+                    let enabled = rewrite.action != RewriteAction::SetStatus;
+                    ui.add_enabled(enabled, TextEdit::singleline(&mut rewrite.name).desired_width(140.0));
+                    if rewrite.action == RewriteAction::ReplaceBody { show_regex_error(ui, &rewrite.name, true); }
+                });
+                let hint = if rewrite.action == RewriteAction::SetStatus { "404" } else { "" };
+                ui.add_enabled(rewrite.action != RewriteAction::RemoveHeader,
+                               TextEdit::singleline(&mut rewrite.value).hint_text(hint).desired_width(140.0));
+            });
+            show_title(ui, "拦截");
+            show_rule_grid(ui, "block_rules", &["启用", "主机", "URL正则", "操作", "状态码", "响应头", "响应body"],
+                           &mut rules.blocks, "添加拦截", Block::new, |ui, i, block| {
+                ui.checkbox(&mut block.enabled, "");
+                ui.add(TextEdit::singleline(&mut block.host).hint_text("*.doubleclick.net").desired_width(160.0));
+                ui.horizontal(|ui| {
+                    ui.add(TextEdit::singleline(&mut block.url).hint_text("/collect\\?").desired_width(160.0));
+                    show_regex_error(ui, block.url.trim(), false);
+                });
+                ComboBox::from_id_salt(("block_action", i)).width(80.0).selected_text(block.action.to_string()).show_ui(ui, |ui| {
+                    for action in BlockAction::actions() {
+                        ui.selectable_value(&mut block.action, action, action.to_string());
+                    }
+                });
+                let respond = block.action == BlockAction::Respond;
+                ui.add_enabled(respond, TextEdit::singleline(&mut block.status).desired_width(100.0));
+                ui.add_enabled(respond, TextEdit::multiline(&mut block.headers).hint_text("Content-Type: text/plain").desired_rows(1).desired_width(160.0));
+                ui.add_enabled(respond, TextEdit::multiline(&mut block.body).desired_rows(1).desired_width(160.0));
+            });
+            show_title(ui, "网络模拟");
+            show_rule_grid(ui, "throttle_rules", &["启用", "主机", "网络", "延迟(ms)", "下行(kbps)", "上行(kbps)", "断开%", "卡住%", "截断%"],
+                           &mut rules.throttles, "添加网络模拟", Throttle::new, |ui, i, throttle| {
+                ui.checkbox(&mut throttle.enabled, "");
+                ui.add(TextEdit::singleline(&mut throttle.host).hint_text("全部").desired_width(160.0));
+                let profile = throttle.profile;
+                ComboBox::from_id_salt(("throttle_profile", i)).width(80.0).selected_text(profile.to_string()).show_ui(ui, |ui| {
+                    for profile in Profile::profiles() {
+                        ui.selectable_value(&mut throttle.profile, profile, profile.to_string());
+                    }
+                });
+                if throttle.profile != profile { throttle.apply_profile(); }
+                //手动改了参数就不再是预设的网络，三个输入框都要画出来，所以先收集再判断
+                let changed = [&mut throttle.latency, &mut throttle.download, &mut throttle.upload].into_iter()
+                    .map(|value| ui.add(DragValue::new(value).range(0..=100_000)).changed())
+                    .collect::<Vec<_>>();
+                if changed.contains(&true) { throttle.profile = Profile::Custom; }
+                for percent in [&mut throttle.reset, &mut throttle.stall, &mut throttle.truncate] {
+                    ui.add(DragValue::new(percent).range(0..=100));
+                }
+            });
+            show_title(ui, "HTTPS解密");
+            ui.label("按CONNECT的主机匹配第一条规则，没有匹配的规则时解密");
+            show_rule_grid(ui, "ssl_rules", &["启用", "主机", "解密", "来源"],
+                           &mut rules.ssl_rules, "添加HTTPS规则", SslRule::new, |ui, _, rule| {
+                ui.checkbox(&mut rule.enabled, "");
+                ui.add(TextEdit::singleline(&mut rule.host).hint_text("全部").desired_width(160.0));
+                ui.checkbox(&mut rule.decrypt, "");
+                ui.label(if rule.auto { "握手失败自动添加" } else { "手动添加" });
+            });
+        });
+        if rules != origin { write_rules(&self.rules).update(rules); }
+        self.rules_window = open;
+    }
+}
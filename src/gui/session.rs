@@ -0,0 +1,102 @@
+use std::path::PathBuf;
+use egui::{Color32, Context, RichText, TextEdit, Window};
+use time::macros::format_description;
+use time::OffsetDateTime;
+use crate::data::http::HttpPacket;
+use crate::data::har::{export_har, import_har};
+use crate::data::session::{decode_session, encode_session};
+use crate::error::{ProxyError, ProxyResult};
+use crate::gui::{ProxyView, LOCAL_OFFSET};
+
+//会话文件默认保存的目录和扩展名
+const SESSION_DIR: &str = "target/tmp/sessions";
+const SESSION_EXT: &str = "pxs";
+const HAR_EXT: &str = "har";
+
+//保存和导出的文件都放在会话目录，文件名是当前时间
+fn session_path(ext: &str) -> ProxyResult<PathBuf> {
+    std::fs::create_dir_all(SESSION_DIR)?;
+    let offset = LOCAL_OFFSET.get().copied().unwrap_or(time::UtcOffset::UTC);
+    let now = OffsetDateTime::now_utc().to_offset(offset);
+    let name = now.format(format_description!("[year][month][day]-[hour][minute][second]"))?;
+    Ok(PathBuf::from(SESSION_DIR).join(format!("{}.{}", name, ext)))
+}
+
+impl ProxyView {
+    //把抓到的所有请求保存到会话目录，文件名是保存的时间
+    pub fn save_session(&mut self) {
+        let res = session_path(SESSION_EXT).and_then(|path| {
+            std::fs::write(&path, encode_session(&self.data))?;
+            Ok(path)
+        });
+        self.set_status_message(res.map(|path| format!("已保存{}个请求到{}", self.data.len(), path.display())), "保存会话失败");
+    }
+
+    //导出列表里当前显示的请求，过滤和搜索之后的结果
+    pub fn export_har(&mut self) {
+        let items = self.visible_items();
+        let packets = items.iter().map(|index| &self.data[*index]).collect::<Vec<_>>();
+        let res = session_path(HAR_EXT).and_then(|path| {
+            std::fs::write(&path, export_har(&packets)?)?;
+            Ok(path)
+        });
+        self.set_status_message(res.map(|path| format!("已导出{}个请求到{}", items.len(), path.display())), "导出HAR失败");
+    }
+
+    //按扩展名区分会话文件和HAR文件
+    fn load_session(&mut self, path: &str) {
+        let res = std::fs::read(path).map_err(ProxyError::from).and_then(|bs| {
+            if path.to_lowercase().ends_with(HAR_EXT) { import_har(&String::from_utf8(bs)?) } else { Ok((decode_session(&bs)?, 0)) }
+        });
+        match res {
+            Ok((packets, skipped)) => {
+                let mut message = format!("已打开{}，共{}个请求", path, packets.len());
+                if skipped > 0 { message.push_str(&format!("，{}个请求无法导入已跳过", skipped)); }
+                self.status_message = Some((message, false));
+                self.set_data(packets);
+                self.open_window = false;
+            }
+            Err(e) => self.set_status_message(Err(e), "打开会话失败"),
+        }
+    }
+
+    //打开会话的窗口，列出会话目录里的文件，也可以输入其他路径
+    pub fn show_open_window(&mut self, ctx: &Context) {
+        if !self.open_window { return; }
+        let mut open = true;
+        let mut selected = None;
+        Window::new("打开会话").open(&mut open).collapsible(false).show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.add(TextEdit::singleline(&mut self.session_path).desired_width(400.0).hint_text("会话文件路径"));
+                ui.button("打开").clicked().then(|| selected = Some(self.session_path.clone()));
+            });
+            ui.separator();
+            let mut files = std::fs::read_dir(SESSION_DIR).map(|dir| {
+                dir.filter_map(|entry| entry.ok()).map(|entry| entry.path())
+                    .filter(|path| path.extension().is_some_and(|ext| ext == SESSION_EXT || ext == HAR_EXT)).collect::<Vec<_>>()
+            }).unwrap_or_default();
+            //最新的放在最前面
+            files.sort_by(|a, b| b.cmp(a));
+            if files.is_empty() { ui.label(format!("{}里没有会话文件", SESSION_DIR)); }
+            for file in files {
+                let path = file.display().to_string();
+                ui.selectable_label(self.session_path == path, &path).clicked().then(|| self.session_path = path.clone());
+            }
+            if let Some((message, true)) = &self.status_message {
+                ui.label(RichText::new(message).color(Color32::RED));
+            }
+        });
+        if let Some(path) = selected { self.load_session(&path); }
+        if !open { self.open_window = false; }
+    }
+
+    //替换列表里的数据，和选中的请求相关的缓存都要清掉
+    pub fn set_data(&mut self, packets: Vec<HttpPacket>) {
+        self.data = packets;
+        self.current_item = None;
+        self.preview = None;
+        self.params = None;
+        self.raw_view = None;
+        self.search_hits.clear();
+    }
+}
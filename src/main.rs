@@ -4,30 +4,27 @@ mod error;
 mod proxy;
 mod data;
 mod gui;
+mod server;
+mod rule;
 
-use std::collections::HashMap;
 use std::io::BufReader;
+use std::net::IpAddr;
 use std::sync::Arc;
-use std::time::Duration;
 use egui::ViewportBuilder;
 use log4rs::append::console::ConsoleAppender;
 use log4rs::append::file::FileAppender;
 use log4rs::Config;
 use log4rs::config::{Appender, Logger, Root};
 use log4rs::encode::pattern::PatternEncoder;
-use log::{debug, error, info, trace, LevelFilter};
+use log::{trace, LevelFilter};
 use rustls::ServerConfig;
 use rustls_pemfile::Item;
 use rustls_pki_types::PrivateKeyDer;
-use tokio::net::TcpListener;
-use tokio::sync;
-use tokio::time::sleep;
-use tokio_rustls::TlsAcceptor;
-use crate::data::{HttpTcpData, ProxyData, StreamDirection};
 use crate::error::ProxyResult;
-use crate::gui::ProxyView;
-use crate::proxy::ProxyStream;
+use crate::gui::{init_local_offset, ProxyView};
 fn main() {
+    init_local_offset();
+    init_log4rs().unwrap_or_else(|e| eprintln!("初始化日志失败：{}", e.to_string()));
     let viewport = ViewportBuilder::default()
         .with_title("Proxy").with_inner_size((1200.0, 6000.0));
     let mut native_options = eframe::NativeOptions::default();
@@ -35,16 +32,6 @@ fn main() {
     eframe::run_native("Proxy", native_options, Box::new(|cc| ProxyView::new(cc))).unwrap();
 }
 
-// #[tokio::main]
-// async fn main() {
-//     tokio::spawn(async {
-//         init_log4rs().unwrap();
-//         start_server().await.unwrap();
-//     });
-//     sleep(Duration::from_secs(100000)).await;
-//     // start_socks5_server().await.unwrap()
-// }
-
 fn init_log4rs() -> ProxyResult<()> {
     let coder = PatternEncoder::new("{h({d(%Y-%m-%d %H:%M:%S)} [{f}:{L}] {l:<6})} {M}:{m}{n}");
     let stdout = ConsoleAppender::builder().encoder(Box::new(coder.clone())).build();
@@ -53,6 +40,10 @@ fn init_log4rs() -> ProxyResult<()> {
         .appender(Appender::builder().build("stdout", Box::new(stdout)))
         .appender(Appender::builder().build("requests", Box::new(requests)))
         .logger(Logger::builder().build("rustls", LevelFilter::Error))
+        //界面相关的日志太多，这里只保留警告
+        .logger(Logger::builder().build("eframe", LevelFilter::Warn))
+        .logger(Logger::builder().build("egui_glow", LevelFilter::Warn))
+        .logger(Logger::builder().build("winit", LevelFilter::Warn))
         .build(Root::builder().appender("stdout").appender("requests").build(LevelFilter::Trace))?;
 
     log4rs::init_config(config)?;
@@ -60,54 +51,6 @@ fn init_log4rs() -> ProxyResult<()> {
 }
 
 
-//我们先在本地启动一个服务，监听7090端口
-async fn start_server() -> ProxyResult<()> {
-    let (sx, rx) = sync::mpsc::channel(1024);
-    tokio::spawn(async move {
-        receive_data(rx).await;
-    });
-    info!("在本地0.0.0.0:7090建立一个Tcp端口监听服务");
-    let listen = TcpListener::bind("0.0.0.0:7090").await?;
-    loop {
-        //接受一个新连接
-        let (stream, addr) = listen.accept().await?;
-        debug!("来自{}的新连接",addr);
-        //启动一个线程，避免造成其他连接阻塞，影响网络体验
-        let sender = sx.clone();
-        tokio::spawn(async move {
-            ProxyStream::new(stream, sender).start().await.unwrap_or_else(|e| error!("{}",e.to_string()));
-        });
-    }
-}
-//到目前为止，我们没有做区分stream
-async fn receive_once(rx: &mut sync::mpsc::Receiver<ProxyData>, data: &mut HashMap<String, HttpTcpData>) -> ProxyResult<()> {
-    match rx.recv().await {
-        None => {}
-        Some(pd) => {
-            let tcp_data = match data.get_mut(pd.stream_id()) {
-                None => {
-                    data.insert(pd.stream_id().to_string(), HttpTcpData::new());
-                    data.get_mut(pd.stream_id()).unwrap()
-                }
-                Some(res) => res,
-            };
-            tcp_data.push(pd)?;
-        }
-    }
-    Ok(())
-}
-
-async fn receive_data(mut rx: sync::mpsc::Receiver<ProxyData>) {
-    let mut data = HashMap::new();
-    loop {
-        match receive_once(&mut rx, &mut data).await {
-            Ok(_) => {}
-            Err(e) => error!("{}",e.to_string()),
-        }
-    }
-}
-
-
 #[allow(dead_code)]
 fn regex_find(rex: &str, context: &str) -> ProxyResult<Vec<String>> {
     let regx = regex::RegexBuilder::new(rex).build()?;
@@ -124,12 +67,17 @@ fn regex_find(rex: &str, context: &str) -> ProxyResult<Vec<String>> {
 }
 
 
-//这里需要实现一个TlsAcceptor才能解密
-fn gen_acceptor_for_sni(sni: impl AsRef<str>) -> ProxyResult<TlsAcceptor> {
+//这里需要一个ServerConfig才能解密，alpn是和客户端协商的应用层协议
+fn gen_config_for_sni(sni: impl AsRef<str>, alpn: Vec<Vec<u8>>) -> ProxyResult<Arc<ServerConfig>> {
     //这里先要生成证书
     //在top命令中我们看到我们的程序在建立连接的时候cpu占用很高。这个是证书生成时占用的，这里我们做一个证书缓存
-    let crt_path = format!("target/tmp/certs/{}.pem", sni.as_ref());
-    let key_path = format!("target/tmp/certs/{}.key", sni.as_ref());
+    //IP的证书写的是IP而不是域名，和以前按域名签发的缓存区分开；IPv6地址里的冒号不能用在文件名里
+    let name = match sni.as_ref().parse::<IpAddr>() {
+        Ok(_) => format!("ip_{}", sni.as_ref().replace(":", "_")),
+        Err(_) => sni.as_ref().to_string(),
+    };
+    let crt_path = format!("target/tmp/certs/{}.pem", name);
+    let key_path = format!("target/tmp/certs/{}.key", name);
     let (sni_bs, key_bs) = if std::fs::exists(crt_path.as_str())? {
         let sni_bs = std::fs::read(crt_path.as_str())?;
         let key_bs = std::fs::read(key_path.as_str())?;
@@ -159,8 +107,8 @@ fn gen_acceptor_for_sni(sni: impl AsRef<str>) -> ProxyResult<TlsAcceptor> {
         Item::Sec1Key(key) => PrivateKeyDer::Sec1(key),
         _ => return Err("不支持的证书密钥类型".into()),
     };
-    let config = ServerConfig::builder_with_protocol_versions(&rustls::ALL_VERSIONS)
+    let mut config = ServerConfig::builder_with_protocol_versions(&rustls::ALL_VERSIONS)
         .with_no_client_auth().with_single_cert(vec![sni_cert], sni_key)?;
-    let acceptor = TlsAcceptor::from(Arc::new(config));
-    Ok(acceptor)
+    config.alpn_protocols = alpn;
+    Ok(Arc::new(config))
 }
\ No newline at end of file
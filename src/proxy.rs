@@ -1,55 +1,107 @@
+use std::collections::VecDeque;
 use std::sync::Arc;
-use log::{error, trace};
+use std::time::{Duration, Instant, SystemTime};
+use log::{error, info, trace};
 use rustls::{ClientConfig, RootCertStore};
-use rustls_pki_types::{DnsName, ServerName};
-use tokio::io::{AsyncReadExt, AsyncWriteExt, ReadHalf, WriteHalf};
+use rustls::server::Acceptor;
+use rustls_pki_types::ServerName;
+use socket2::SockRef;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadHalf, WriteHalf};
 use tokio::net::TcpStream;
 use tokio::sync;
 use tokio::task::{JoinError, JoinHandle};
-use tokio_rustls::TlsConnector;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::{LazyConfigAcceptor, TlsConnector};
 use uuid::Uuid;
 use crate::error::{ProxyError, ProxyResult};
-use crate::{gen_acceptor_for_sni, regex_find};
-use crate::data::{ProxyData, StreamDirection};
+use crate::{gen_config_for_sni, regex_find};
+use crate::data::{ProxyData, ProxyEvent, StreamDirection, StreamInfo, TlsInfo};
+use crate::data::http::parser::HttpParser;
+use crate::data::http::{split_authority, split_url, HttpData, HttpPacket, HttpVersion};
+use crate::rule::block::Block;
+use crate::rule::breakpoint::{build_message, response_has_body, Paused, Resume};
+use crate::rule::map_local::local_response;
+use crate::rule::rewrite::{apply_rewrites, Rewrite};
+use crate::rule::throttle::{Fault, Shaper, Throttle};
+use crate::rule::{head_text, read_rules, write_rules, Phase, SharedRules};
+
+//代理两端的连接，可能是TCP也可能是TLS
+pub trait ProxyIo: AsyncRead + AsyncWrite + Send + Sync + Unpin {
+    //底层的TCP连接，模拟连接被重置时要用
+    fn tcp(&self) -> Option<&TcpStream> {
+        None
+    }
+}
+
+impl ProxyIo for TcpStream {
+    fn tcp(&self) -> Option<&TcpStream> {
+        Some(self)
+    }
+}
+
+impl ProxyIo for tokio_rustls::server::TlsStream<TcpStream> {
+    fn tcp(&self) -> Option<&TcpStream> {
+        Some(self.get_ref().0)
+    }
+}
+
+impl ProxyIo for TlsStream<TcpStream> {}
+
+#[cfg(test)]
+impl ProxyIo for tokio::io::DuplexStream {}
 
 //
 pub struct ProxyStream {
     //生成一个id以便区分流
     stream_id: String,
     inbound: TcpStream,
-    sender: sync::mpsc::Sender<ProxyData>,
+    sender: sync::mpsc::Sender<ProxyEvent>,
+    rules: SharedRules,
+    //被断点拦住的报文交给界面
+    pauses: sync::mpsc::Sender<Paused>,
 }
 
 impl ProxyStream {
-    pub fn new(inbound: TcpStream, sender: sync::mpsc::Sender<ProxyData>) -> ProxyStream {
+    pub fn new(inbound: TcpStream, sender: sync::mpsc::Sender<ProxyEvent>, rules: SharedRules, pauses: sync::mpsc::Sender<Paused>) -> ProxyStream {
         ProxyStream {
             inbound,
             sender,
+            rules,
+            pauses,
             stream_id: Uuid::new_v4().to_string(),
         }
     }
 
     async fn copy<'a, I, O>(mut reader: ReadHalf<I>, mut writer: WriteHalf<O>, direction: StreamDirection,
-                            sender: sync::mpsc::Sender<ProxyData>, stream_id: String) -> JoinHandle<ProxyResult<()>>
+                            sender: sync::mpsc::Sender<ProxyEvent>, stream_id: String, shaper: Shaper) -> JoinHandle<ProxyResult<()>>
     where
         I: AsyncReadExt + Send + Unpin + 'static,
         O: AsyncWriteExt + Send + Unpin + 'static,
     {
         tokio::spawn(async move {
+            let mut last_time: Option<Instant> = None;
             loop {
                 let mut buffer = [0; 4096];
                 let len = reader.read(&mut buffer).await?;
+                //模拟慢速网络：空闲一段时间之后的新数据先等待延迟，连续的数据只按带宽限速
+                if len > 0 && last_time.is_none_or(|t| t.elapsed() >= shaper.latency()) {
+                    sleep_for(shaper.latency()).await;
+                }
+                shaper.pace(len).await;
+                last_time = Some(Instant::now());
                 //及时把数据发送出去，减少延时
                 writer.write(&buffer[..len]).await?;
-                if len == 0 { break; } //读取长度为0时，此tcp连接已断开
+                //读取长度为0时，此tcp连接已断开，也通知一下数据处理端
                 let data = ProxyData::new(direction.clone(), buffer, len, stream_id.clone());
-                sender.send(data).await?;
+                sender.send(ProxyEvent::Data(Box::new(data))).await?;
+                if len == 0 { break; }
             }
             Ok::<(), ProxyError>(())
         })
     }
 
-    async fn copy_io<I, O>(inbound: I, outbound: O, sender: sync::mpsc::Sender<ProxyData>, stream_id: String) -> ProxyResult<()>
+    async fn copy_io<I, O>(inbound: I, outbound: O, sender: sync::mpsc::Sender<ProxyEvent>, stream_id: String,
+                           up: Shaper, down: Shaper) -> ProxyResult<()>
     where
         I: AsyncReadExt + AsyncWriteExt + Send + Unpin + 'static,
         O: AsyncReadExt + AsyncWriteExt + Send + Unpin + 'static,
@@ -65,59 +117,57 @@ impl ProxyStream {
         };
         let (inbound_reader, inbound_writer) = tokio::io::split(inbound);
         let (outbound_reader, outbound_writer) = tokio::io::split(outbound);
-        let rt1 = ProxyStream::copy(inbound_reader, outbound_writer, StreamDirection::ClientToServer, sender.clone(), stream_id.clone()).await;
-        let rt2 = ProxyStream::copy(outbound_reader, inbound_writer, StreamDirection::ServerToClient, sender, stream_id).await;
+        let rt1 = ProxyStream::copy(inbound_reader, outbound_writer, StreamDirection::ClientToServer, sender.clone(), stream_id.clone(), up).await;
+        let rt2 = ProxyStream::copy(outbound_reader, inbound_writer, StreamDirection::ServerToClient, sender, stream_id, down).await;
         let (r1, r2) = tokio::join!(rt1,rt2);
         res_func(r1, StreamDirection::ClientToServer);
         res_func(r2, StreamDirection::ServerToClient);
         Ok(())
     }
 
+    //普通的HTTP代理，同一个客户端连接上可以有多个请求，每个请求都按自己的地址转发
     async fn handle_http(self, buffer: [u8; 4096], len: usize) -> ProxyResult<()> {
-        let http_prefix = b"http://";
-        let start_pos = buffer.windows(http_prefix.len()).position(|b| b == http_prefix).ok_or("获取HTTP地址失败")?;
-        let end_pos = buffer[start_pos + http_prefix.len()..len].iter().position(|b| *b == b'/').ok_or("获取HTTP地址失败")? + start_pos + http_prefix.len();
-        println!("{} {}", start_pos, end_pos);
-        //获取真实服务器地址，端口为80的会自动省略
-        let addr = String::from_utf8(buffer[start_pos + http_prefix.len()..end_pos].to_vec())?;
-        println!("{}", addr);
-        let host = addr.split(":").next().unwrap();
-        let port = match addr.contains(":") {
-            true => addr.split(":").last().ok_or("获取端口失败")?.parse::<u16>()?,
-            false => 80
-        };
-        // 这里我们就拿到了真实的服务器地址
-        println!("{}:{}", host, port);
-        //与真实服务器建立连接，并把两个stream相互复制
-        let mut outbound = TcpStream::connect(format!("{}:{}", host, port)).await?;
-        // outbound.write(&buffer[..len]).await?;
-        outbound.write(&buffer[..start_pos]).await?;
-        outbound.write(&buffer[end_pos..len]).await?;
-        ProxyStream::copy_io(self.inbound, outbound, self.sender, self.stream_id).await
-    }
-
-    async fn handle_https(mut self, buffer: [u8; 4096], len: usize) -> ProxyResult<()> {
-        let info = String::from_utf8(buffer[..len].to_vec())?;
-        let addr = regex_find("CONNECT (.*?) ", info.as_str())?;
-        if addr.len() == 0 { return Err("获取HTTPS真实地址失败".into()); }
+        let exchange = Exchange::new(self.stream_id, self.sender, self.rules, self.pauses, Box::new(self.inbound), None);
+        exchange.run(&buffer[..len]).await
+    }
+
+    async fn handle_https(mut self, addr: &str) -> ProxyResult<()> {
         self.inbound.write(b"HTTP/1.1 200 OK\r\n\r\n").await?;
         self.inbound.flush().await?;
         //从这里开始，两个stream之间交互的就是真实的https数据了
-        let sni = addr[0].split(":").next().unwrap();
-        trace!("已解析到https地址：{}；SNI：{}",addr[0],sni);
-        let acceptor = gen_acceptor_for_sni(sni)?;
-        let inbound = acceptor.accept(self.inbound).await?;
-        let mut root_ca = RootCertStore::empty();
-        root_ca.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
-        let client_config = ClientConfig::builder().with_root_certificates(root_ca).with_no_client_auth();
-        let outbound = TcpStream::connect(&addr[0]).await?;
-        let connector = TlsConnector::from(Arc::new(client_config));
-        let server_name = ServerName::DnsName(DnsName::try_from(sni.to_string())?);
-        let outbound = connector.connect(server_name, outbound).await?;
+        //先读ClientHello，证书按客户端真正要访问的名字签发；没有SNI时（比如直接CONNECT到IP）用CONNECT里的主机
+        let start = LazyConfigAcceptor::new(Acceptor::default(), self.inbound).await?;
+        let hello = start.client_hello();
+        let sni = match hello.server_name() {
+            Some(sni) => sni.to_string(),
+            None => split_authority(addr, 443)?.0.trim_start_matches("[").trim_end_matches("]").to_string(),
+        };
+        //客户端提供的ALPN原样提供给服务器，只是代理只解析HTTP/1.x，去掉h2和h3
+        let alpn: Vec<Vec<u8>> = hello.alpn().map(|protocols| protocols.filter(|p| !matches!(*p, b"h2" | b"h3")).map(|p| p.to_vec()).collect())
+            .unwrap_or_default();
+        trace!("已解析到https地址：{}；SNI：{}",addr,sni);
+        let config = gen_config_for_sni(&sni, alpn.clone())?;
+        //只有客户端回了告警才算不接受代理的证书，握手中途断开可能只是客户端不要这个连接了
+        let inbound = match start.into_stream(config).await {
+            Ok(inbound) => inbound,
+            Err(e) => {
+                if alert_received(&e) { record_handshake(&self.rules, addr, false); }
+                return Err(e.into());
+            }
+        };
+        record_handshake(&self.rules, addr, true);
         // //这里我们就实现了HTTPS解密，但是我们的根证书还没安装
         // //sudo cp sca.pem /etc/pki/ca-trust/source/anchors/
         // //sudo update-ca-trust
-        ProxyStream::copy_io(inbound, outbound, self.sender, self.stream_id).await
+        let tunnel = Some(Tunnel { addr: addr.to_string(), sni, alpn });
+        let exchange = Exchange::new(self.stream_id, self.sender, self.rules, self.pauses, Box::new(inbound), tunnel);
+        exchange.run(&[]).await
+    }
+
+    //不解密的HTTPS，和服务器建立TCP连接后原样转发
+    async fn handle_tunnel(self, connect: &[u8], addr: &str) -> ProxyResult<()> {
+        let exchange = Exchange::new(self.stream_id, self.sender, self.rules, self.pauses, Box::new(self.inbound), None);
+        exchange.tunnel(connect, addr).await
     }
 
     pub async fn start(mut self) -> ProxyResult<()> {
@@ -125,10 +175,671 @@ impl ProxyStream {
         let len = self.inbound.read(&mut buffer).await?;
 
         if buffer.starts_with(b"CONNECT") {
-            self.handle_https(buffer, len).await?;
+            let info = String::from_utf8(buffer[..len].to_vec())?;
+            let addr = regex_find("CONNECT (.*?) ", info.as_str())?;
+            if addr.len() == 0 { return Err("获取HTTPS真实地址失败".into()); }
+            //只按主机拦截的规则在建立隧道之前就生效
+            let block = read_rules(&self.rules).block_connect(&addr[0]);
+            let decrypt = read_rules(&self.rules).decrypt(&addr[0]);
+            match block {
+                Some(block) => {
+                    let exchange = Exchange::new(self.stream_id, self.sender, self.rules, self.pauses, Box::new(self.inbound), None);
+                    exchange.refuse_connect(&block, &buffer[..len], &addr[0]).await?;
+                }
+                None if !decrypt => self.handle_tunnel(&buffer[..len], &addr[0]).await?,
+                None => self.handle_https(&addr[0]).await?,
+            }
         } else {
             self.handle_http(buffer, len).await?;
         }
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+//解密之后的一个客户端连接，上面的请求逐个转发，HTTP和HTTPS共用
+struct Exchange {
+    stream_id: String,
+    sender: sync::mpsc::Sender<ProxyEvent>,
+    rules: SharedRules,
+    pauses: sync::mpsc::Sender<Paused>,
+    inbound: Box<dyn ProxyIo>,
+    //HTTPS的服务器地址和SNI由CONNECT决定，HTTP按每个请求的地址转发
+    tunnel: Option<Tunnel>,
+    //上游连接可以复用，地址变了才重新建立连接
+    upstream: Option<(String, Box<dyn ProxyIo>, StreamInfo)>,
+    //最近一次通知给数据处理端的连接信息
+    announced: Option<StreamInfo>,
+    //最近一个请求用到的网络环境模拟，协议升级后继续使用
+    throttle: Option<Throttle>,
+}
+
+//CONNECT的地址和客户端ClientHello里的SNI、ALPN，连接这个地址时照着用
+struct Tunnel {
+    addr: String,
+    sni: String,
+    alpn: Vec<Vec<u8>>,
+}
+
+//一个请求实际要发往的地方
+struct Target {
+    scheme: String,
+    addr: String,
+    //实际发给服务器的数据
+    raw: Vec<u8>,
+    //Map Remote改写前的URL
+    remap: Option<String>,
+}
+
+impl Target {
+    fn key(&self) -> String {
+        format!("{}://{}", self.scheme, self.addr)
+    }
+}
+
+//一个请求处理完之后怎么继续
+enum Next {
+    Continue,
+    Close,
+    //协议已经升级，后面的数据直接相互复制
+    Upgrade,
+    //模拟连接被重置，不正常关闭直接发RST
+    Reset,
+}
+
+impl Exchange {
+    fn new(stream_id: String, sender: sync::mpsc::Sender<ProxyEvent>, rules: SharedRules, pauses: sync::mpsc::Sender<Paused>,
+           inbound: Box<dyn ProxyIo>, tunnel: Option<Tunnel>) -> Exchange {
+        Exchange { stream_id, sender, rules, pauses, inbound, tunnel, upstream: None, announced: None, throttle: None }
+    }
+
+    fn scheme(&self) -> &'static str {
+        if self.tunnel.is_some() { "https" } else { "http" }
+    }
+
+    async fn run(mut self, buffer: &[u8]) -> ProxyResult<()> {
+        let mut parser = HttpParser::new(StreamDirection::ClientToServer);
+        let mut reqs = VecDeque::from(parser.push(buffer, SystemTime::now())?);
+        let mut first = buffer.is_empty();
+        loop {
+            let Some(req) = reqs.pop_front() else {
+                let mut buffer = [0; 4096];
+                let len = self.inbound.read(&mut buffer).await?;
+                if len == 0 { break; } //客户端断开了
+                //隧道里不是HTTP时不解析，原样转发
+                if first && self.tunnel.is_some() && !looks_like_http(&buffer[..len]) {
+                    return self.passthrough(&buffer[..len]).await;
+                }
+                first = false;
+                reqs.extend(parser.push(&buffer[..len], SystemTime::now())?);
+                continue;
+            };
+            first = false;
+            match self.handle_request(req).await? {
+                Next::Continue => {}
+                Next::Close => break,
+                Next::Reset => {
+                    //SO_LINGER设成0之后关闭连接发的是RST而不是FIN
+                    if let Some(tcp) = self.inbound.tcp() { SockRef::from(tcp).set_linger(Some(Duration::ZERO))?; }
+                    return self.send_close().await;
+                }
+                Next::Upgrade => {
+                    let (_, outbound, _) = self.upstream.take().ok_or("服务器已断开连接")?;
+                    let (up, down) = shapers(self.throttle.as_ref());
+                    return ProxyStream::copy_io(self.inbound, outbound, self.sender, self.stream_id, up, down).await;
+                }
+            }
+        }
+        self.inbound.shutdown().await?;
+        self.send_close().await
+    }
+
+    //CONNECT和它的响应配成一条记录，之后隧道里的数据数据处理端不再解析
+    async fn tunnel(mut self, connect: &[u8], addr: &str) -> ProxyResult<()> {
+        let outbound = TcpStream::connect(addr).await?;
+        let established = b"HTTP/1.1 200 OK\r\n\r\n";
+        self.inbound.write_all(established).await?;
+        let mut info = StreamInfo::new(&self.stream_id, "https", addr);
+        info.set_tunneled(true);
+        self.announce(info, true).await?;
+        self.send_data(StreamDirection::ClientToServer, connect).await?;
+        self.send_data(StreamDirection::ServerToClient, established).await?;
+        let throttle = read_rules(&self.rules).throttle(addr);
+        let (up, down) = shapers(throttle.as_ref());
+        ProxyStream::copy_io(self.inbound, outbound, self.sender, self.stream_id, up, down).await
+    }
+
+    async fn passthrough(mut self, buffer: &[u8]) -> ProxyResult<()> {
+        let addr = self.tunnel.as_ref().map(|tunnel| tunnel.addr.clone()).ok_or("获取HTTPS真实地址失败")?;
+        let (mut outbound, info) = self.connect("https", &addr).await?;
+        self.announce(info, true).await?;
+        let throttle = read_rules(&self.rules).throttle(&addr);
+        let (up, down) = shapers(throttle.as_ref());
+        sleep_for(up.latency()).await;
+        write_paced(&mut outbound, buffer, &up).await?;
+        self.send_data(StreamDirection::ClientToServer, buffer).await?;
+        ProxyStream::copy_io(self.inbound, outbound, self.sender, self.stream_id, up, down).await
+    }
+
+    async fn handle_request(&mut self, req: HttpData) -> ProxyResult<Next> {
+        let url = self.url_of(&req);
+        let method = req.header().method().to_string();
+        trace!("{} {}", method, url);
+        let block = read_rules(&self.rules).block(&url);
+        if let Some(block) = block {
+            return match block.response() {
+                Some(raw) => self.respond(&req, raw, vec![], true).await,
+                //断开连接时只记录请求，数据处理端在连接断开后补上空的响应
+                None => {
+                    let target = self.target_of(&req)?;
+                    let mut info = StreamInfo::new(&self.stream_id, target.scheme, target.addr);
+                    info.set_blocked(true);
+                    self.announce(info, false).await?;
+                    self.send_data(StreamDirection::ClientToServer, req.raw()).await?;
+                    Ok(Next::Close)
+                }
+            };
+        }
+        //改写规则先于断点，断点里看到的是改写后的请求
+        let mut notes = vec![];
+        let rewrites = read_rules(&self.rules).rewrites(Phase::Request, &method, &url);
+        let req = rewrite_data(&rewrites, &req, None, &mut notes)?.unwrap_or(req);
+        let req = match self.pause(Phase::Request, &req, &method, &url).await {
+            None => req,
+            Some(Resume::Continue(head, body)) => {
+                if edited(&req, &head, &body) { notes.push("请求断点修改".to_string()); }
+                parse_message(StreamDirection::ClientToServer, &resumed_message(&req, &head, &body, None), None)?
+            }
+            Some(Resume::Abort) => return Ok(Next::Close),
+            //不发给服务器，直接回复客户端
+            Some(Resume::Respond(head, body)) => {
+                notes.push("请求断点返回响应".to_string());
+                let has_body = response_has_body(&head, req.header().method());
+                return self.respond(&req, build_message(&head, &body, has_body), notes, false).await;
+            }
+        };
+        let url = self.url_of(&req);
+        let method = req.header().method().to_string();
+        let map_local = read_rules(&self.rules).map_local(&method, &url);
+        if let Some(map_local) = map_local {
+            let file = map_local.local_file(&url);
+            let body = match &file {
+                Some(file) => tokio::fs::read(file).await.ok(),
+                None => None,
+            };
+            notes.push(format!("本地映射：{}", file.as_ref().map(|f| f.display().to_string()).unwrap_or_default()));
+            return self.respond(&req, local_response(&method, file.as_deref(), body), notes, false).await;
+        }
+        let map_remote = read_rules(&self.rules).map_remote(&method, &url);
+        let target = match map_remote.and_then(|rule| Some((rule.rewrite(&url)?, rule.keep_host))) {
+            Some((to, keep_host)) => remote_target(&req, &url, &to, keep_host)?,
+            None => self.target_of(&req)?,
+        };
+        let key = target.key();
+        let authority = split_url(&url).map(|(_, authority, _)| authority.to_string()).unwrap_or_default();
+        self.throttle = read_rules(&self.rules).throttle(&authority);
+        let (up, down) = shapers(self.throttle.as_ref());
+        let fault = self.throttle.as_ref().and_then(|throttle| throttle.roll_fault());
+        if let Some(fault) = fault { notes.push(format!("故障注入：{}", fault)); }
+        let res_rewrites = read_rules(&self.rules).rewrites(Phase::Response, &method, &url);
+        //响应要改写、要停在断点或者要模拟故障时，先不发给客户端
+        let hold = fault.is_some() || !res_rewrites.is_empty() || read_rules(&self.rules).breakpoint(Phase::Response, &method, &url);
+        sleep_for(up.latency()).await;
+        //复用的连接可能已经被服务器关掉了，这时换一个新连接重试一次；
+        //新连接上失败不再重试，否则POST这种请求可能被服务器处理两次
+        let mut relayed = loop {
+            let (mut outbound, mut info, fresh) = match self.upstream.take() {
+                Some((upstream_key, outbound, info)) if upstream_key == key => (outbound, info, false),
+                _ => {
+                    let (outbound, info) = self.connect(&target.scheme, &target.addr).await?;
+                    (outbound, info, true)
+                }
+            };
+            info.set_remap(target.remap.clone());
+            info.set_rewrites(notes.clone());
+            //新连接上重发的请求不能和之前没有响应的请求混在一起，所以新连接总是重新通知
+            self.announce(info.clone(), fresh).await?;
+            let written = write_paced(&mut outbound, &target.raw, &up).await;
+            if let Err(e) = written {
+                if fresh { return Err(e); }
+                trace!("复用的连接已断开：{}", e.to_string());
+                continue;
+            }
+            //先拦住的响应改完才知道有没有被修改，请求也等到那时再交给数据处理端
+            let request = if hold { None } else { Some(target.raw.as_slice()) };
+            match self.relay_response(&mut outbound, &method, request, &down).await? {
+                Some(relayed) => {
+                    self.upstream = Some((key.clone(), outbound, info));
+                    break relayed;
+                }
+                None if fresh => return Err("服务器已断开连接".into()),
+                None => {}
+            }
+        };
+        if hold {
+            let mut raw = std::mem::take(&mut relayed.held);
+            if let Some(res) = relayed.res.as_ref().filter(|_| !relayed.upgraded)
+                && let Some(res) = rewrite_data(&res_rewrites, res, Some(&method), &mut notes)? {
+                raw = res.raw().to_vec();
+                relayed.res = Some(res);
+            }
+            let resume = match &relayed.res {
+                Some(res) if !relayed.upgraded => self.pause(Phase::Response, res, &method, &url).await,
+                _ => None,
+            };
+            match (resume, relayed.res.as_ref()) {
+                (Some(Resume::Continue(head, body)) | Some(Resume::Respond(head, body)), Some(res)) => {
+                    if edited(res, &head, &body) { notes.push("响应断点修改".to_string()); }
+                    raw = resumed_message(res, &head, &body, Some(&method));
+                    relayed.res = Some(parse_message(StreamDirection::ServerToClient, &raw, Some(&method))?);
+                }
+                (Some(Resume::Abort), _) => return Ok(Next::Close),
+                _ => {}
+            }
+            if let Some(mut info) = self.announced.clone() {
+                info.set_rewrites(notes);
+                self.announce(info, false).await?;
+            }
+            self.send_data(StreamDirection::ClientToServer, &target.raw).await?;
+            //协议升级的响应不模拟故障；数据处理端记录的是服务器返回的完整响应
+            let fault = fault.filter(|_| !relayed.upgraded);
+            match fault {
+                None => write_paced(&mut self.inbound, &raw, &down).await?,
+                Some(Fault::Reset) => {}
+                //响应头完整发出，body只发一半
+                Some(Fault::Truncate) => {
+                    let head_len = raw.windows(4).position(|w| w == b"\r\n\r\n").map(|p| p + 4).unwrap_or(raw.len());
+                    write_paced(&mut self.inbound, &raw[..head_len + (raw.len() - head_len) / 2], &down).await?;
+                }
+                Some(Fault::Stall) => {
+                    //等客户端自己断开，最多等一会儿
+                    let mut buffer = [0; 4096];
+                    let _ = tokio::time::timeout(STALL_TIMEOUT, async {
+                        while self.inbound.read(&mut buffer).await.is_ok_and(|len| len > 0) {}
+                    }).await;
+                }
+            }
+            self.send_data(StreamDirection::ServerToClient, &raw).await?;
+            match fault {
+                None => {}
+                Some(Fault::Reset) => return Ok(Next::Reset),
+                Some(_) => return Ok(Next::Close),
+            }
+        }
+        if relayed.upgraded { return Ok(Next::Upgrade); }
+        //响应头解析失败时不知道服务器会不会保持连接，直接换新连接
+        match &relayed.res {
+            Some(res) if !relayed.closed && !wants_close(res) => {}
+            _ => self.upstream = None,
+        }
+        //没有长度的响应是靠断开连接结束的，客户端这边也要断开
+        if relayed.closed || wants_close(&req) { return Ok(Next::Close); }
+        Ok(Next::Continue)
+    }
+
+    //不经过服务器，直接用raw回复客户端，请求和响应照常交给数据处理端
+    async fn respond(&mut self, req: &HttpData, raw: Vec<u8>, notes: Vec<String>, blocked: bool) -> ProxyResult<Next> {
+        let res = parse_message(StreamDirection::ServerToClient, &raw, Some(req.header().method()))?;
+        self.inbound.write_all(&raw).await?;
+        let target = self.target_of(req)?;
+        let mut info = StreamInfo::new(&self.stream_id, target.scheme, target.addr);
+        info.set_rewrites(notes);
+        info.set_blocked(blocked);
+        self.announce(info, false).await?;
+        self.send_data(StreamDirection::ClientToServer, req.raw()).await?;
+        self.send_data(StreamDirection::ServerToClient, &raw).await?;
+        Ok(if wants_close(req) || wants_close(&res) { Next::Close } else { Next::Continue })
+    }
+
+    //CONNECT被拦截时不建立隧道，拒绝时回复403，断开时什么也不回复，两种情况都记录下来
+    async fn refuse_connect(mut self, block: &Block, connect: &[u8], addr: &str) -> ProxyResult<()> {
+        let mut info = StreamInfo::new(&self.stream_id, "https", addr);
+        info.set_blocked(true);
+        self.announce(info, true).await?;
+        self.send_data(StreamDirection::ClientToServer, connect).await?;
+        if let Some(raw) = block.response() {
+            self.inbound.write_all(&raw).await?;
+            self.send_data(StreamDirection::ServerToClient, &raw).await?;
+        }
+        self.inbound.shutdown().await?;
+        self.send_close().await
+    }
+
+    //匹配断点时把报文交给界面，等界面决定怎么继续，界面已经关闭时按原样继续
+    async fn pause(&self, phase: Phase, data: &HttpData, method: &str, url: &str) -> Option<Resume> {
+        if !read_rules(&self.rules).breakpoint(phase, method, url) { return None; }
+        let (paused, rx) = Paused::new(phase, method, url, data.raw(), data.body().decoded());
+        self.pauses.send(paused).await.ok()?;
+        rx.await.ok()
+    }
+
+    //完整的URL，用来匹配规则
+    fn url_of(&self, req: &HttpData) -> String {
+        let uri = req.header().uri();
+        if split_url(uri).is_some() { return uri.to_string(); }
+        let host = req.header().get("Host").map(|h| h.to_string())
+            .or(self.tunnel.as_ref().map(|tunnel| tunnel.addr.clone())).unwrap_or_default();
+        format!("{}://{}{}", self.scheme(), host, uri)
+    }
+
+    //没有改写时请求发往原来的地址
+    fn target_of(&self, req: &HttpData) -> ProxyResult<Target> {
+        let (addr, raw) = match &self.tunnel {
+            Some(tunnel) => (tunnel.addr.clone(), req.raw().to_vec()),
+            None => to_origin_form(req, 80)?,
+        };
+        Ok(Target { scheme: self.scheme().to_string(), addr, raw, remap: None })
+    }
+
+    //建立上游连接，同时返回连接信息；HTTPS隧道的原地址用客户端的SNI和ALPN，改写后的地址用新的主机名
+    async fn connect(&self, scheme: &str, addr: &str) -> ProxyResult<(Box<dyn ProxyIo>, StreamInfo)> {
+        let mut info = StreamInfo::new(&self.stream_id, scheme, addr);
+        if scheme != "https" { return Ok((Box::new(TcpStream::connect(addr).await?), info)); }
+        let (sni, alpn) = match &self.tunnel {
+            Some(tunnel) if tunnel.addr == addr => (tunnel.sni.clone(), tunnel.alpn.clone()),
+            _ => (split_authority(addr, 443)?.0.trim_start_matches("[").trim_end_matches("]").to_string(), vec![]),
+        };
+        let (outbound, tls) = tls_connect(addr, &sni, alpn).await?;
+        info.set_tls(tls);
+        Ok((Box::new(outbound), info))
+    }
+
+    //连接信息变了才通知数据处理端，数据处理端收到后会重新开始解析这个连接
+    async fn announce(&mut self, info: StreamInfo, force: bool) -> ProxyResult<()> {
+        if !force && self.announced.as_ref() == Some(&info) { return Ok(()); }
+        self.sender.send(ProxyEvent::Open(Box::new(info.clone()))).await?;
+        self.announced = Some(info);
+        Ok(())
+    }
+
+    //把数据按4096字节分块交给数据处理端
+    async fn send_data(&self, direction: StreamDirection, bs: &[u8]) -> ProxyResult<()> {
+        for chunk in bs.chunks(4096) {
+            let mut buffer = [0; 4096];
+            buffer[..chunk.len()].copy_from_slice(chunk);
+            let data = ProxyData::new(direction.clone(), buffer, chunk.len(), self.stream_id.clone());
+            self.sender.send(ProxyEvent::Data(Box::new(data))).await?;
+        }
+        Ok(())
+    }
+
+    //长度为0的数据表示连接断开
+    async fn send_close(&self) -> ProxyResult<()> {
+        for direction in [StreamDirection::ClientToServer, StreamDirection::ServerToClient] {
+            let data = ProxyData::new(direction, [0; 4096], 0, self.stream_id.clone());
+            self.sender.send(ProxyEvent::Data(Box::new(data))).await?;
+        }
+        Ok(())
+    }
+
+    //把响应原样转发给客户端，直到一个完整的响应结束；上游连接断开且没有收到任何数据时返回None
+    //hold为true时响应先不发给客户端，放在held里等断点处理完
+    //服务器没有任何响应就断开时返回None；request为空时响应先拦住不发；不为空时是还没交给数据处理端的请求，收到响应的第一块数据才交，
+    //这样复用的连接已经断开、换新连接重试时，不会留下一个没有响应的请求
+    async fn relay_response(&mut self, outbound: &mut Box<dyn ProxyIo>, method: &str, request: Option<&[u8]>, down: &Shaper) -> ProxyResult<Option<Relayed>> {
+        let mut parser = HttpParser::new(StreamDirection::ServerToClient);
+        parser.push_method(method);
+        let mut held = vec![];
+        let mut received = false;
+        loop {
+            let mut buffer = [0; 4096];
+            //还没收到响应时读取出错（比如连接被服务器重置）和直接断开一样处理
+            let len = match outbound.read(&mut buffer).await {
+                Ok(len) => len,
+                Err(e) if !received => {
+                    trace!("服务器没有响应：{}", e.to_string());
+                    0
+                }
+                Err(e) => return Err(e.into()),
+            };
+            if len == 0 {
+                if !received { return Ok(None); }
+                let res = parser.finish()?;
+                return Ok(Some(Relayed { res, held, upgraded: false, closed: true }));
+            }
+            if let (false, Some(request)) = (received, request) { self.send_data(StreamDirection::ClientToServer, request).await?; }
+            received = true;
+            if request.is_none() {
+                held.extend(&buffer[..len]);
+            } else {
+                down.pace(len).await;
+                self.inbound.write_all(&buffer[..len]).await?;
+                self.send_data(StreamDirection::ServerToClient, &buffer[..len]).await?;
+            }
+            let res = parser.push(&buffer[..len], SystemTime::now())?.pop();
+            if parser.completed() > 0 {
+                return Ok(Some(Relayed { res, held, upgraded: parser.is_opaque(), closed: false }));
+            }
+        }
+    }
+}
+
+//客户端连续几次握手失败之后，这个地址不再解密
+fn record_handshake(rules: &SharedRules, addr: &str, ok: bool) {
+    let mut rules = write_rules(rules);
+    if ok {
+        rules.handshake_succeeded(addr);
+    } else if rules.handshake_failed(addr) {
+        info!("{}多次握手失败，之后不再解密", addr);
+    }
+}
+
+//握手失败是因为收到了客户端的TLS告警，比如bad_certificate、unknown_ca
+fn alert_received(e: &std::io::Error) -> bool {
+    e.get_ref().and_then(|e| e.downcast_ref::<rustls::Error>()).is_some_and(|e| matches!(e, rustls::Error::AlertReceived(_)))
+}
+
+//响应卡住时最多等这么久
+const STALL_TIMEOUT: Duration = Duration::from_secs(60);
+
+//两个方向的限速，没有匹配的规则时不限制
+fn shapers(throttle: Option<&Throttle>) -> (Shaper, Shaper) {
+    match throttle {
+        Some(throttle) => (throttle.shaper(StreamDirection::ClientToServer), throttle.shaper(StreamDirection::ServerToClient)),
+        None => (Shaper::default(), Shaper::default()),
+    }
+}
+
+async fn sleep_for(duration: Duration) {
+    if !duration.is_zero() { tokio::time::sleep(duration).await; }
+}
+
+//按带宽分块写出
+async fn write_paced<W: AsyncWrite + Unpin>(writer: &mut W, bs: &[u8], shaper: &Shaper) -> ProxyResult<()> {
+    for chunk in bs.chunks(4096) {
+        shaper.pace(chunk.len()).await;
+        writer.write_all(chunk).await?;
+    }
+    Ok(())
+}
+
+//HTTP请求以方法名开头，方法名都是大写字母
+fn looks_like_http(bs: &[u8]) -> bool {
+    let method_len = bs.iter().take_while(|b| b.is_ascii_uppercase()).count();
+    method_len > 0 && (method_len == bs.len() || bs[method_len] == b' ')
+}
+
+//应用改写规则，有改动时返回改写后重新解析的报文，method为None时是请求
+fn rewrite_data(rewrites: &[Rewrite], data: &HttpData, method: Option<&str>, notes: &mut Vec<String>) -> ProxyResult<Option<HttpData>> {
+    let Some((head, body, applied)) = apply_rewrites(rewrites, &head_text(data.raw()), data.body().raw(), data.body().decoded()) else {
+        return Ok(None);
+    };
+    notes.extend(applied);
+    let direction = if method.is_some() { StreamDirection::ServerToClient } else { StreamDirection::ClientToServer };
+    let has_body = method.is_none_or(|method| response_has_body(&head, method));
+    Ok(Some(parse_message(direction, &build_message(&head, &body, has_body), method)?))
+}
+
+//断点里编辑的是解压后的body，没改body时还用原来的字节；改了就去掉压缩方式的字段，和改写规则一样
+fn resumed_message(data: &HttpData, head: &str, body: &[u8], method: Option<&str>) -> Vec<u8> {
+    let has_body = method.is_none_or(|method| response_has_body(head, method));
+    if body == data.body().decoded() { return build_message(head, data.body().raw(), has_body); }
+    if !data.body().is_encoded() { return build_message(head, body, has_body); }
+    let head = head.lines().filter(|line| !line.split(":").next().unwrap_or("").trim().eq_ignore_ascii_case("Content-Encoding"))
+        .collect::<Vec<_>>().join("\n");
+    build_message(&head, body, has_body)
+}
+
+//断点里有没有修改过报文
+fn edited(data: &HttpData, head: &str, body: &[u8]) -> bool {
+    head_text(data.raw()) != head || data.body().decoded() != body
+}
+
+//把一个完整的报文解析出来，没有长度的响应按数据结束处理
+fn parse_message(direction: StreamDirection, raw: &[u8], method: Option<&str>) -> ProxyResult<HttpData> {
+    let mut parser = HttpParser::new(direction);
+    if let Some(method) = method { parser.push_method(method); }
+    let mut datas = parser.push(raw, SystemTime::now())?;
+    if datas.is_empty() { datas.extend(parser.finish()?); }
+    Ok(datas.into_iter().next().ok_or("报文不完整")?)
+}
+
+//和服务器建立TLS连接，同时返回协商的结果；sni是IP时按IP校验证书
+async fn tls_connect(addr: &str, sni: &str, alpn: Vec<Vec<u8>>) -> ProxyResult<(TlsStream<TcpStream>, TlsInfo)> {
+    let mut root_ca = RootCertStore::empty();
+    root_ca.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    let mut client_config = ClientConfig::builder().with_root_certificates(root_ca).with_no_client_auth();
+    client_config.alpn_protocols = alpn;
+    let outbound = TcpStream::connect(addr).await?;
+    let connector = TlsConnector::from(Arc::new(client_config));
+    let server_name = ServerName::try_from(sni.to_string())?;
+    let outbound = connector.connect(server_name, outbound).await?;
+    let (_, conn) = outbound.get_ref();
+    let version = conn.protocol_version().map(|v| format!("{:?}", v)).unwrap_or_default();
+    let cipher = conn.negotiated_cipher_suite().map(|c| format!("{:?}", c.suite())).unwrap_or_default();
+    let alpn = conn.alpn_protocol().map(|p| String::from_utf8_lossy(p).to_string()).unwrap_or_default();
+    Ok((outbound, TlsInfo::new(sni, version, cipher, alpn)))
+}
+
+//重新发送一个请求，raw是完整的请求报文，请求行里是完整的URL，连接服务器的方式和代理转发时一样
+pub async fn replay(raw: Vec<u8>) -> ProxyResult<HttpPacket> {
+    let time = SystemTime::now();
+    let req = HttpParser::new(StreamDirection::ClientToServer).push(&raw, time)?.pop().ok_or("请求报文不完整")?;
+    let scheme = split_url(req.header().uri()).map(|(scheme, _, _)| scheme.to_lowercase()).unwrap_or("http".to_string());
+    let default_port = if scheme == "https" { 443 } else { 80 };
+    let (addr, raw) = to_origin_form(&req, default_port)?;
+    let mut info = StreamInfo::new(Uuid::new_v4(), &scheme, &addr);
+    let res = if scheme == "https" {
+        let (host, _) = split_authority(&addr, default_port)?;
+        let (mut outbound, tls) = tls_connect(&addr, host.trim_start_matches("[").trim_end_matches("]"), vec![]).await?;
+        info.set_tls(tls);
+        exchange(&mut outbound, &raw, req.header().method()).await?
+    } else {
+        exchange(&mut TcpStream::connect(&addr).await?, &raw, req.header().method()).await?
+    };
+    //记录的请求是实际发出去的样子
+    let req = HttpParser::new(StreamDirection::ClientToServer).push(&raw, time)?.pop().ok_or("请求报文不完整")?;
+    Ok(HttpPacket::from_data(info, req, res))
+}
+
+//发送请求并读取一个完整的响应
+async fn exchange<S>(outbound: &mut S, raw: &[u8], method: &str) -> ProxyResult<HttpData>
+where
+    S: AsyncReadExt + AsyncWriteExt + Unpin,
+{
+    outbound.write_all(raw).await?;
+    let mut parser = HttpParser::new(StreamDirection::ServerToClient);
+    parser.push_method(method);
+    loop {
+        let mut buffer = [0; 4096];
+        let len = outbound.read(&mut buffer).await?;
+        if len == 0 { return Ok(parser.finish()?.ok_or("服务器已断开连接")?); }
+        if let Some(res) = parser.push(&buffer[..len], SystemTime::now())?.pop() { return Ok(res); }
+        if parser.completed() > 0 { return Err("响应头解析失败".into()); }
+    }
+}
+
+//一次响应转发的结果
+struct Relayed {
+    res: Option<HttpData>,
+    //断点拦住的响应原始数据
+    held: Vec<u8>,
+    //协议已经升级，后面的数据不再是HTTP
+    upgraded: bool,
+    //服务器断开了连接
+    closed: bool,
+}
+
+//代理收到的请求URI是完整地址，转发给服务器之前要改成只有路径的形式，同时返回服务器地址
+fn to_origin_form(req: &HttpData, default_port: u16) -> ProxyResult<(String, Vec<u8>)> {
+    let uri = req.header().uri();
+    let (authority, path) = match split_url(uri) {
+        Some((_, authority, path)) => (authority.to_string(), path),
+        None => (req.header().get("Host").ok_or("获取HTTP地址失败")?.to_string(), uri.to_string()),
+    };
+    let (host, port) = split_authority(&authority, default_port)?;
+    let raw = req.raw();
+    let line_end = raw.windows(2).position(|w| w == b"\r\n").ok_or("HTTP数据错误")?;
+    let line = String::from_utf8_lossy(&raw[..line_end]).to_string();
+    let version = line.rsplit(" ").next().unwrap_or("HTTP/1.1");
+    let mut res = format!("{} {} {}", req.header().method(), path, version).into_bytes();
+    //HTTP/1.1要求必须有Host
+    if req.header().get("Host").is_none() {
+        res.extend(format!("\r\nHost: {}", authority).as_bytes());
+    }
+    res.extend(&raw[line_end..]);
+    Ok((format!("{}:{}", host, port), res))
+}
+
+//Map Remote改写之后的请求，请求行换成新的路径，不保留Host时Host也换成新的地址
+fn remote_target(req: &HttpData, from: &str, to: &str, keep_host: bool) -> ProxyResult<Target> {
+    let (scheme, authority, path) = split_url(to).ok_or("Map Remote地址错误")?;
+    let scheme = scheme.to_lowercase();
+    let (host, port) = split_authority(authority, if scheme == "https" { 443 } else { 80 })?;
+    let raw = req.raw();
+    let head_end = raw.windows(4).position(|w| w == b"\r\n\r\n").ok_or("HTTP数据错误")?;
+    let mut lines = raw[..head_end].split(|b| *b == b'\n').map(|line| line.strip_suffix(b"\r").unwrap_or(line));
+    let line = String::from_utf8_lossy(lines.next().unwrap_or(b"")).to_string();
+    let version = line.rsplit(" ").next().unwrap_or("HTTP/1.1");
+    let mut res = format!("{} {} {}", req.header().method(), path, version).into_bytes();
+    let mut has_host = false;
+    for line in lines {
+        let key = String::from_utf8_lossy(line.split(|b| *b == b':').next().unwrap_or(b"")).trim().to_string();
+        let is_host = key.eq_ignore_ascii_case("Host");
+        has_host |= is_host;
+        res.extend(b"\r\n");
+        if is_host && !keep_host {
+            res.extend(format!("Host: {}", authority).as_bytes());
+        } else {
+            res.extend(line);
+        }
+    }
+    if !has_host { res.extend(format!("\r\nHost: {}", authority).as_bytes()); }
+    res.extend(&raw[head_end..]);
+    Ok(Target { scheme, addr: format!("{}:{}", host, port), raw: res, remap: Some(from.to_string()) })
+}
+
+//报文带有Connection: close，或者是HTTP/1.0且没有keep-alive时，处理完之后要断开连接
+fn wants_close(data: &HttpData) -> bool {
+    let connection = data.header().get("Connection").or(data.header().get("Proxy-Connection")).unwrap_or("");
+    if connection.eq_ignore_ascii_case("close") { return true; }
+    matches!(data.header().version(), HttpVersion::Http10) && !connection.eq_ignore_ascii_case("keep-alive")
+}
+
+
+#[cfg(test)]
+mod test_proxy {
+    use std::io::{Error, ErrorKind};
+    use rustls::AlertDescription;
+    use crate::proxy::{alert_received, Exchange, Tunnel};
+    use crate::rule::{read_rules, Rules};
+
+    #[tokio::test]
+    async fn test_handshake_eof() {
+        assert!(alert_received(&Error::new(ErrorKind::InvalidData, rustls::Error::AlertReceived(AlertDescription::BadCertificate))));
+        assert!(!alert_received(&Error::from(ErrorKind::UnexpectedEof)));
+
+        //握手成功后没发请求就断开（比如浏览器预连接）不算证书被拒绝
+        let rules = Rules::shared();
+        let addr = "a.com:443";
+        for _ in 0..5 {
+            let (client, server) = tokio::io::duplex(4096);
+            drop(client);
+            let (sender, _rx) = tokio::sync::mpsc::channel(16);
+            let tunnel = Some(Tunnel { addr: addr.to_string(), sni: "a.com".to_string(), alpn: vec![] });
+            let exchange = Exchange::new("1".to_string(), sender, rules.clone(), tokio::sync::mpsc::channel(1).0, Box::new(server), tunnel);
+            exchange.run(&[]).await.unwrap();
+        }
+        assert!(read_rules(&rules).decrypt(addr));
+        assert!(read_rules(&rules).ssl_rules.is_empty());
+    }
+}
@@ -0,0 +1,120 @@
+use std::fmt::{Display, Formatter};
+use regex::RegexBuilder;
+use crate::data::http::{split_url, HttpStatus};
+use crate::rule::breakpoint::{build_message, response_has_body};
+use crate::rule::host_matches;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum BlockAction {
+    //HTTPS在CONNECT时就回复403，HTTP请求回复403后断开
+    Refuse,
+    //什么也不回复，直接断开连接
+    Close,
+    //回复配置好的响应
+    Respond,
+}
+
+impl BlockAction {
+    pub fn actions() -> [BlockAction; 3] {
+        [BlockAction::Refuse, BlockAction::Close, BlockAction::Respond]
+    }
+}
+
+impl Display for BlockAction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BlockAction::Refuse => f.write_str("拒绝"),
+            BlockAction::Close => f.write_str("断开连接"),
+            BlockAction::Respond => f.write_str("返回响应"),
+        }
+    }
+}
+
+//拦截广告、统计之类的请求，主机支持*通配符，URL是正则表达式，空的条件匹配所有
+#[derive(Clone, PartialEq)]
+pub struct Block {
+    pub enabled: bool,
+    pub host: String,
+    pub url: String,
+    pub action: BlockAction,
+    //返回响应时用到的状态码、头字段（每行一个）和body
+    pub status: String,
+    pub headers: String,
+    pub body: String,
+}
+
+impl Block {
+    pub fn new() -> Block {
+        Block {
+            enabled: true,
+            host: String::new(),
+            url: String::new(),
+            action: BlockAction::Refuse,
+            status: "204 No Content".to_string(),
+            headers: String::new(),
+            body: String::new(),
+        }
+    }
+
+    //URL的正则写错时规则不起作用
+    pub fn matches(&self, url: &str) -> bool {
+        let authority = split_url(url).map(|(_, authority, _)| authority).unwrap_or("");
+        if !host_matches(&self.host, authority) { return false; }
+        let pattern = self.url.trim();
+        pattern.is_empty() || RegexBuilder::new(pattern).case_insensitive(true).build().is_ok_and(|r| r.is_match(url))
+    }
+
+    //CONNECT时只知道主机，只有不限制URL的规则才能在这时拦截
+    pub fn matches_connect(&self, authority: &str) -> bool {
+        self.url.trim().is_empty() && self.action != BlockAction::Respond && host_matches(&self.host, authority)
+    }
+
+    //拦截之后回复给客户端的完整响应，断开连接时没有响应
+    pub fn response(&self) -> Option<Vec<u8>> {
+        match self.action {
+            BlockAction::Refuse => Some(build_message("HTTP/1.1 403 Forbidden\nConnection: close\nContent-Type: text/plain; charset=utf-8",
+                                                      "请求已被代理拦截".as_bytes(), true)),
+            BlockAction::Close => None,
+            BlockAction::Respond => {
+                //只写了状态码时补上标准的原因短语
+                let status = match self.status.trim() {
+                    "" => "204 No Content".to_string(),
+                    code if !code.contains(" ") => match code.parse::<u16>() {
+                        Ok(code) => format!("{} {}", code, HttpStatus::from_code(code, "").reason()),
+                        Err(_) => code.to_string(),
+                    },
+                    status => status.to_string(),
+                };
+                let head = format!("HTTP/1.1 {}\n{}", status, self.headers);
+                Some(build_message(&head, self.body.as_bytes(), response_has_body(&head, "GET")))
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test_block {
+    use crate::rule::block::{Block, BlockAction};
+
+    #[test]
+    fn test_block() {
+        let mut block = Block::new();
+        block.host = "*.doubleclick.net".to_string();
+        assert!(block.matches("https://ad.doubleclick.net/pixel?x=1"));
+        assert!(block.matches_connect("ad.doubleclick.net:443"));
+        assert!(!block.matches("https://example.com/"));
+        block.url = r"/collect\?".to_string();
+        assert!(block.matches("https://stats.doubleclick.net:8443/COLLECT?v=1"));
+        assert!(!block.matches("https://stats.doubleclick.net/pixel"));
+        assert!(!block.matches_connect("stats.doubleclick.net:443"));
+
+        block.action = BlockAction::Respond;
+        block.status = "200".to_string();
+        block.headers = "Content-Type: application/json\n".to_string();
+        block.body = "{}".to_string();
+        assert_eq!(block.response().unwrap(), b"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 2\r\n\r\n{}");
+        block.action = BlockAction::Close;
+        assert!(block.response().is_none());
+    }
+}
@@ -0,0 +1,126 @@
+use tokio::sync::oneshot;
+use crate::rule::{head_text, Phase, RuleMatcher};
+
+#[derive(Clone, PartialEq)]
+pub struct Breakpoint {
+    pub enabled: bool,
+    pub matcher: RuleMatcher,
+    pub request: bool,
+    pub response: bool,
+}
+
+impl Breakpoint {
+    pub fn new() -> Breakpoint {
+        Breakpoint { enabled: true, matcher: RuleMatcher::default(), request: true, response: false }
+    }
+
+    pub fn phase_enabled(&self, phase: Phase) -> bool {
+        match phase {
+            Phase::Request => self.request,
+            Phase::Response => self.response,
+        }
+    }
+}
+
+//被断点拦住的报文，界面编辑之后通过reply告诉代理怎么继续
+pub struct Paused {
+    phase: Phase,
+    method: String,
+    url: String,
+    //起始行和头字段，换行统一成\n方便编辑
+    head: String,
+    //解压后的body，方便直接编辑
+    body: Vec<u8>,
+    reply: oneshot::Sender<Resume>,
+}
+
+pub enum Resume {
+    //按编辑后的内容继续
+    Continue(String, Vec<u8>),
+    //断开客户端连接
+    Abort,
+    //不发给服务器，直接用这个响应回复客户端，只有请求断点可以用
+    Respond(String, Vec<u8>),
+}
+
+impl Paused {
+    pub fn new(phase: Phase, method: &str, url: &str, raw: &[u8], body: &[u8]) -> (Paused, oneshot::Receiver<Resume>) {
+        let (reply, rx) = oneshot::channel();
+        let paused = Paused { phase, method: method.to_string(), url: url.to_string(), head: head_text(raw), body: body.to_vec(), reply };
+        (paused, rx)
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn head(&self) -> &str {
+        &self.head
+    }
+
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    //连接已经断开时代理那边收不到，忽略就行
+    pub fn resume(self, resume: Resume) {
+        let _ = self.reply.send(resume);
+    }
+}
+
+//把编辑后的头和body拼成完整的报文，body已经不是chunked了，长度按实际的body重新计算
+//has_body为false时报文按协议没有body（比如HEAD请求的响应、304），长度字段原样保留
+pub fn build_message(head: &str, body: &[u8], has_body: bool) -> Vec<u8> {
+    let mut lines = head.lines().map(|line| line.trim_end()).filter(|line| !line.is_empty()).collect::<Vec<_>>();
+    let had_length = lines.iter().skip(1).any(|line| {
+        let key = line.split(":").next().unwrap_or("").trim();
+        key.eq_ignore_ascii_case("Content-Length") || key.eq_ignore_ascii_case("Transfer-Encoding")
+    });
+    if has_body {
+        lines.retain(|line| {
+            let key = line.split(":").next().unwrap_or("").trim();
+            !key.eq_ignore_ascii_case("Content-Length") && !key.eq_ignore_ascii_case("Transfer-Encoding")
+        });
+    }
+    let mut res = lines.join("\r\n");
+    if has_body && (had_length || !body.is_empty()) {
+        res.push_str(&format!("\r\nContent-Length: {}", body.len()));
+    }
+    res.push_str("\r\n\r\n");
+    let mut res = res.into_bytes();
+    if has_body { res.extend(body); }
+    res
+}
+
+//响应有没有body，head是响应的起始行和头字段，method是对应请求的方法
+pub fn response_has_body(head: &str, method: &str) -> bool {
+    let code = head.split_whitespace().nth(1).and_then(|code| code.parse::<u16>().ok()).unwrap_or(200);
+    !(method.eq_ignore_ascii_case("HEAD") || code < 200 || code == 204 || code == 304)
+}
+
+
+#[cfg(test)]
+mod test_breakpoint {
+    use crate::rule::breakpoint::{build_message, response_has_body};
+
+    #[test]
+    fn test_build_message() {
+        let head = "HTTP/1.1 200 OK\nTransfer-Encoding: chunked\nX-A: 1\n";
+        assert_eq!(build_message(head, b"hello", true), b"HTTP/1.1 200 OK\r\nX-A: 1\r\nContent-Length: 5\r\n\r\nhello");
+        assert_eq!(build_message("GET / HTTP/1.1\nHost: a.com", b"", true), b"GET / HTTP/1.1\r\nHost: a.com\r\n\r\n");
+        //HEAD请求的响应和304的长度字段说的是完整资源的长度，不能改成0
+        let head = "HTTP/1.1 304 Not Modified\nContent-Length: 5";
+        assert!(!response_has_body(head, "GET"));
+        assert!(!response_has_body("HTTP/1.1 200 OK", "HEAD"));
+        assert!(response_has_body("HTTP/1.1 200 OK", "GET"));
+        assert_eq!(build_message(head, b"", false), b"HTTP/1.1 304 Not Modified\r\nContent-Length: 5\r\n\r\n");
+    }
+}
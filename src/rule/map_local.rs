@@ -0,0 +1,116 @@
+use std::path::{Path, PathBuf};
+use crate::data::http::param::url_decode;
+use crate::data::http::split_url;
+use crate::rule::RuleMatcher;
+
+//匹配的请求不发给服务器，直接用本地文件回复
+#[derive(Clone, PartialEq)]
+pub struct MapLocal {
+    pub enabled: bool,
+    pub matcher: RuleMatcher,
+    //本地的文件或者目录
+    pub local: String,
+}
+
+impl MapLocal {
+    pub fn new() -> MapLocal {
+        MapLocal { enabled: true, matcher: RuleMatcher::default(), local: String::new() }
+    }
+
+    //映射到目录时，请求路径去掉规则路径里*前面的部分，剩下的拼到目录后面；路径里有..时不映射
+    pub fn local_file(&self, url: &str) -> Option<PathBuf> {
+        let local = Path::new(self.local.trim());
+        if !local.is_dir() { return Some(local.to_path_buf()); }
+        let path = match split_url(url) {
+            Some((_, _, path)) => path,
+            None => url.to_string(),
+        };
+        let path = url_decode(path.split(['?', '#']).next().unwrap_or(""), false);
+        let prefix = self.matcher.path.trim().split("*").next().unwrap_or("");
+        let rest = match path.get(..prefix.len()) {
+            Some(head) if head.eq_ignore_ascii_case(prefix) => &path[prefix.len()..],
+            _ => path.as_str(),
+        };
+        let mut file = local.to_path_buf();
+        for segment in rest.split(['/', '\\']).filter(|s| !s.is_empty() && *s != ".") {
+            if segment == ".." { return None; }
+            file.push(segment);
+        }
+        if file.is_dir() { file.push("index.html"); }
+        Some(file)
+    }
+}
+
+//按扩展名确定Content-Type，不认识的按二进制处理
+pub fn content_type_of(file: &Path) -> &'static str {
+    let ext = file.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+    match ext.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "application/javascript; charset=utf-8",
+        "json" | "map" => "application/json; charset=utf-8",
+        "xml" => "application/xml; charset=utf-8",
+        "txt" | "log" => "text/plain; charset=utf-8",
+        "csv" => "text/csv; charset=utf-8",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "bmp" => "image/bmp",
+        "avif" => "image/avif",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        "mp3" => "audio/mpeg",
+        "wasm" => "application/wasm",
+        "pdf" => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
+
+//用本地文件拼出完整的响应，文件读取失败时返回404，HEAD请求只有头
+pub fn local_response(method: &str, file: Option<&Path>, body: Option<Vec<u8>>) -> Vec<u8> {
+    let (status, content_type, body) = match (file, body) {
+        (Some(file), Some(body)) => ("200 OK", content_type_of(file), body),
+        (file, _) => {
+            let name = file.map(|f| f.display().to_string()).unwrap_or_default();
+            ("404 Not Found", "text/plain; charset=utf-8", format!("本地文件不存在：{}", name).into_bytes())
+        }
+    };
+    let mut res = format!("HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nAccess-Control-Allow-Origin: *\r\n\r\n",
+                          status, content_type, body.len()).into_bytes();
+    if !method.eq_ignore_ascii_case("HEAD") { res.extend(body); }
+    res
+}
+
+
+#[cfg(test)]
+mod test_map_local {
+    use std::path::Path;
+    use crate::rule::map_local::{local_response, MapLocal};
+    use crate::rule::RuleMatcher;
+
+    #[test]
+    fn test_local_file() {
+        let dir = std::env::temp_dir().join("proxy_test_map_local");
+        std::fs::create_dir_all(dir.join("js")).unwrap();
+        let mut rule = MapLocal::new();
+        rule.matcher = RuleMatcher { host: "a.com".to_string(), path: "/static/*".to_string(), method: String::new() };
+        rule.local = dir.display().to_string();
+        assert_eq!(rule.local_file("https://a.com/static/js/app%20v1.js?v=2"), Some(dir.join("js").join("app v1.js")));
+        assert_eq!(rule.local_file("https://a.com/static/js/"), Some(dir.join("js").join("index.html")));
+        assert_eq!(rule.local_file("https://a.com/static/../secret"), None);
+        rule.local = "/tmp/app.js".to_string();
+        assert_eq!(rule.local_file("https://a.com/static/other.js"), Some(Path::new("/tmp/app.js").to_path_buf()));
+
+        let res = local_response("GET", Some(Path::new("a.css")), Some(b"body{}".to_vec()));
+        assert!(res.starts_with(b"HTTP/1.1 200 OK\r\nContent-Type: text/css; charset=utf-8\r\nContent-Length: 6\r\n"));
+        assert!(res.ends_with(b"\r\n\r\nbody{}"));
+        assert!(local_response("HEAD", Some(Path::new("a.css")), None).starts_with(b"HTTP/1.1 404 Not Found"));
+    }
+}
@@ -0,0 +1,88 @@
+use crate::data::http::{split_authority, split_url};
+use crate::rule::RuleMatcher;
+
+//把匹配的请求转发到另一个地址，空的部分保持原样
+#[derive(Clone, PartialEq)]
+pub struct MapRemote {
+    pub enabled: bool,
+    pub matcher: RuleMatcher,
+    pub scheme: String,
+    pub host: String,
+    pub port: String,
+    //替换规则路径里*前面的部分
+    pub path: String,
+    //保留原来的Host，不改成新的地址
+    pub keep_host: bool,
+}
+
+fn default_port(scheme: &str) -> u16 {
+    if scheme.eq_ignore_ascii_case("https") { 443 } else { 80 }
+}
+
+impl MapRemote {
+    pub fn new() -> MapRemote {
+        MapRemote {
+            enabled: true,
+            matcher: RuleMatcher::default(),
+            scheme: String::new(),
+            host: String::new(),
+            port: String::new(),
+            path: String::new(),
+            keep_host: false,
+        }
+    }
+
+    //返回改写后的完整URL，URL或者规则里的端口不对时不改写
+    pub fn rewrite(&self, url: &str) -> Option<String> {
+        let (scheme, authority, path) = split_url(url)?;
+        let (host, port) = split_authority(authority, default_port(scheme)).ok()?;
+        let new_scheme = match self.scheme.trim() {
+            "" => scheme.to_lowercase(),
+            s => s.to_lowercase(),
+        };
+        let new_host = match self.host.trim() {
+            "" => host,
+            h => h.to_string(),
+        };
+        //只改了协议时，默认端口跟着协议变
+        let new_port = match self.port.trim() {
+            "" if port == default_port(scheme) => default_port(&new_scheme),
+            "" => port,
+            p => p.parse::<u16>().ok()?,
+        };
+        let prefix = self.matcher.path.trim().split("*").next().unwrap_or("");
+        let new_path = match (self.path.trim(), path.get(..prefix.len())) {
+            ("", _) => path.clone(),
+            (p, Some(head)) if head.eq_ignore_ascii_case(prefix) => format!("{}{}", p, &path[prefix.len()..]),
+            (p, _) => p.to_string(),
+        };
+        let new_path = if new_path.starts_with("/") { new_path } else { format!("/{}", new_path) };
+        if new_port == default_port(&new_scheme) {
+            Some(format!("{}://{}{}", new_scheme, new_host, new_path))
+        } else {
+            Some(format!("{}://{}:{}{}", new_scheme, new_host, new_port, new_path))
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test_map_remote {
+    use crate::rule::map_remote::MapRemote;
+    use crate::rule::RuleMatcher;
+
+    #[test]
+    fn test_rewrite() {
+        let mut rule = MapRemote::new();
+        rule.matcher = RuleMatcher { host: "api.prod.example.com".to_string(), path: "/v1/*".to_string(), method: String::new() };
+        rule.host = "staging.local".to_string();
+        assert_eq!(rule.rewrite("https://api.prod.example.com/v1/users?id=1").unwrap(), "https://staging.local/v1/users?id=1");
+        rule.scheme = "http".to_string();
+        rule.path = "/api/v2/".to_string();
+        assert_eq!(rule.rewrite("https://api.prod.example.com/v1/users?id=1").unwrap(), "http://staging.local/api/v2/users?id=1");
+        rule.port = "8080".to_string();
+        assert_eq!(rule.rewrite("https://api.prod.example.com:8443/v1/").unwrap(), "http://staging.local:8080/api/v2/");
+        rule.port = "http".to_string();
+        assert_eq!(rule.rewrite("https://api.prod.example.com/v1/"), None);
+    }
+}
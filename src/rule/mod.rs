@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use crate::data::http::split_url;
+use crate::rule::block::Block;
+use crate::rule::breakpoint::Breakpoint;
+use crate::rule::map_local::MapLocal;
+use crate::rule::map_remote::MapRemote;
+use crate::rule::rewrite::Rewrite;
+use crate::rule::ssl::SslRule;
+use crate::rule::throttle::Throttle;
+
+pub mod breakpoint;
+pub mod block;
+pub mod map_local;
+pub mod map_remote;
+pub mod rewrite;
+pub mod ssl;
+pub mod throttle;
+
+//界面上配置的规则，代理的每个连接都会读取，界面修改后立即生效
+pub type SharedRules = Arc<RwLock<Rules>>;
+
+//规则作用在请求还是响应上
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    //请求发给服务器之前
+    Request,
+    //响应返回给客户端之前
+    Response,
+}
+
+#[derive(Clone, Default, PartialEq)]
+pub struct Rules {
+    pub breakpoints: Vec<Breakpoint>,
+    pub map_locals: Vec<MapLocal>,
+    pub map_remotes: Vec<MapRemote>,
+    pub rewrites: Vec<Rewrite>,
+    pub blocks: Vec<Block>,
+    pub throttles: Vec<Throttle>,
+    pub ssl_rules: Vec<SslRule>,
+    //每个地址连续握手失败的次数
+    handshake_failures: HashMap<String, u32>,
+}
+
+//客户端连续这么多次不接受代理的证书，就不再解密这个地址
+const AUTO_PASSTHROUGH_FAILURES: u32 = 3;
+
+impl Rules {
+    pub fn shared() -> SharedRules {
+        Arc::new(RwLock::new(Rules::default()))
+    }
+
+    pub fn breakpoint(&self, phase: Phase, method: &str, url: &str) -> bool {
+        self.breakpoints.iter().any(|b| b.enabled && b.phase_enabled(phase) && b.matcher.matches(method, url))
+    }
+
+    //多条规则都匹配时用第一条
+    pub fn map_local(&self, method: &str, url: &str) -> Option<MapLocal> {
+        self.map_locals.iter().find(|m| m.enabled && m.matcher.matches(method, url)).cloned()
+    }
+
+    pub fn map_remote(&self, method: &str, url: &str) -> Option<MapRemote> {
+        self.map_remotes.iter().find(|m| m.enabled && m.matcher.matches(method, url)).cloned()
+    }
+
+    pub fn block(&self, url: &str) -> Option<Block> {
+        self.blocks.iter().find(|b| b.enabled && b.matches(url)).cloned()
+    }
+
+    pub fn block_connect(&self, authority: &str) -> Option<Block> {
+        self.blocks.iter().find(|b| b.enabled && b.matches_connect(authority)).cloned()
+    }
+
+    //按连接的主机匹配，多条规则都匹配时用第一条
+    pub fn throttle(&self, authority: &str) -> Option<Throttle> {
+        self.throttles.iter().find(|t| t.enabled && t.matches(authority)).cloned()
+    }
+
+    //按CONNECT的地址匹配第一条规则，没有匹配的规则时解密
+    pub fn decrypt(&self, authority: &str) -> bool {
+        self.ssl_rules.iter().find(|r| r.enabled && r.matches(authority)).is_none_or(|r| r.decrypt)
+    }
+
+    //界面编辑的是规则的副本，改完写回来，握手失败的计数还用这边的
+    pub fn update(&mut self, edited: Rules) {
+        let handshake_failures = std::mem::take(&mut self.handshake_failures);
+        *self = Rules { handshake_failures, ..edited };
+    }
+
+    //多半是客户端固定了证书，返回true表示已经改为不解密；自动规则放在最前面，不会被其他规则挡住
+    pub fn handshake_failed(&mut self, authority: &str) -> bool {
+        let count = self.handshake_failures.entry(authority.to_string()).or_default();
+        *count += 1;
+        if *count < AUTO_PASSTHROUGH_FAILURES { return false; }
+        self.handshake_failures.remove(authority);
+        self.ssl_rules.insert(0, SslRule::auto(authority));
+        true
+    }
+
+    pub fn handshake_succeeded(&mut self, authority: &str) {
+        self.handshake_failures.remove(authority);
+    }
+
+    //所有匹配的改写规则按顺序都会应用
+    pub fn rewrites(&self, phase: Phase, method: &str, url: &str) -> Vec<Rewrite> {
+        self.rewrites.iter().filter(|r| r.enabled && r.phase == phase && r.matcher.matches(method, url)).cloned().collect()
+    }
+}
+
+//起始行和头字段，换行统一成\n方便编辑和改写
+pub fn head_text(raw: &[u8]) -> String {
+    let head_end = raw.windows(4).position(|w| w == b"\r\n\r\n").unwrap_or(raw.len());
+    String::from_utf8_lossy(&raw[..head_end]).replace("\r\n", "\n")
+}
+
+//某个连接出错导致锁中毒时，规则本身并没有损坏，继续使用
+pub fn read_rules(rules: &SharedRules) -> RwLockReadGuard<'_, Rules> {
+    rules.read().unwrap_or_else(|e| e.into_inner())
+}
+
+pub fn write_rules(rules: &SharedRules) -> RwLockWriteGuard<'_, Rules> {
+    rules.write().unwrap_or_else(|e| e.into_inner())
+}
+
+//按主机、路径和请求方法匹配请求，主机和路径支持*通配符，空的条件匹配所有
+#[derive(Clone, Default, PartialEq)]
+pub struct RuleMatcher {
+    pub host: String,
+    pub path: String,
+    pub method: String,
+}
+
+impl RuleMatcher {
+    pub fn matches(&self, method: &str, url: &str) -> bool {
+        let (host, path) = match split_url(url) {
+            Some((_, authority, path)) => (authority.to_string(), path),
+            None => (String::new(), url.to_string()),
+        };
+        let host_matched = host_matches(&self.host, &host);
+        //路径不写查询参数时忽略查询参数
+        let path_only = path.split("?").next().unwrap_or("");
+        let path_matched = self.path.trim().is_empty() || wildcard(self.path.trim(), &path) || wildcard(self.path.trim(), path_only);
+        let method_matched = self.method.trim().is_empty() || self.method.trim().eq_ignore_ascii_case(method);
+        host_matched && path_matched && method_matched
+    }
+}
+
+//主机支持*通配符，可以不写端口，空的条件匹配所有
+pub fn host_matches(pattern: &str, authority: &str) -> bool {
+    let pattern = pattern.trim();
+    if pattern.is_empty() { return true; }
+    let name = authority.rsplit_once(":").filter(|(_, port)| port.bytes().all(|b| b.is_ascii_digit()))
+        .map(|(name, _)| name).unwrap_or(authority);
+    wildcard(pattern, authority) || wildcard(pattern, name)
+}
+
+//*匹配任意多个字符，不区分大小写
+pub fn wildcard(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.to_lowercase().chars().collect::<Vec<_>>();
+    let text = text.to_lowercase().chars().collect::<Vec<_>>();
+    let (mut p, mut t) = (0, 0);
+    //上一个*的位置和当时匹配到的文本位置，失配时回到这里让*多吃一个字符
+    let mut star = None;
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == text[t] {
+            p += 1;
+            t += 1;
+        } else if let Some((sp, st)) = star {
+            p = sp + 1;
+            t = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+
+#[cfg(test)]
+mod test_rule {
+    use crate::rule::{wildcard, RuleMatcher};
+
+    #[test]
+    fn test_matcher() {
+        assert!(wildcard("*.example.com", "api.Example.com"));
+        assert!(wildcard("/api/*/users", "/api/v1/users"));
+        assert!(!wildcard("/api/*/users", "/api/v1/users/1"));
+        assert!(wildcard("a*b*c", "aXbYbZc"));
+
+        let matcher = RuleMatcher { host: "*.example.com".to_string(), path: "/login*".to_string(), method: "post".to_string() };
+        assert!(matcher.matches("POST", "https://api.example.com:8443/login?next=/"));
+        assert!(!matcher.matches("GET", "https://api.example.com/login"));
+        assert!(!matcher.matches("POST", "https://example.org/login"));
+        assert!(RuleMatcher::default().matches("GET", "http://a.com/"));
+    }
+}
@@ -0,0 +1,162 @@
+use std::fmt::{Display, Formatter};
+use regex::bytes::Regex;
+use crate::data::http::HttpStatus;
+use crate::rule::{Phase, RuleMatcher};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum RewriteAction {
+    AddHeader,
+    //有这个字段时替换，没有时添加
+    SetHeader,
+    RemoveHeader,
+    //按正则替换body的内容
+    ReplaceBody,
+    //只对响应有效
+    SetStatus,
+}
+
+impl RewriteAction {
+    pub fn actions() -> [RewriteAction; 5] {
+        [RewriteAction::AddHeader, RewriteAction::SetHeader, RewriteAction::RemoveHeader, RewriteAction::ReplaceBody, RewriteAction::SetStatus]
+    }
+}
+
+impl Display for RewriteAction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RewriteAction::AddHeader => f.write_str("添加头"),
+            RewriteAction::SetHeader => f.write_str("修改头"),
+            RewriteAction::RemoveHeader => f.write_str("删除头"),
+            RewriteAction::ReplaceBody => f.write_str("替换内容"),
+            RewriteAction::SetStatus => f.write_str("修改状态码"),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub struct Rewrite {
+    pub enabled: bool,
+    pub matcher: RuleMatcher,
+    pub phase: Phase,
+    pub action: RewriteAction,
+    //头字段名，替换内容时是正则表达式
+    pub name: String,
+    //头字段值、替换后的内容（可以用$1引用分组）或者状态码
+    pub value: String,
+}
+
+impl Rewrite {
+    pub fn new() -> Rewrite {
+        Rewrite {
+            enabled: true,
+            matcher: RuleMatcher::default(),
+            phase: Phase::Request,
+            action: RewriteAction::AddHeader,
+            name: String::new(),
+            value: String::new(),
+        }
+    }
+
+    //标记在被修改的请求上，说明改了什么
+    pub fn describe(&self) -> String {
+        let phase = if self.phase == Phase::Request { "请求" } else { "响应" };
+        match self.action {
+            RewriteAction::ReplaceBody => format!("{}{}：{} → {}", phase, self.action, self.name.trim(), self.value),
+            RewriteAction::SetStatus => format!("{}{}：{}", phase, self.action, self.value.trim()),
+            _ => format!("{}{}：{}", phase, self.action, self.name.trim()),
+        }
+    }
+
+    //改一次报文，name为空、正则或者状态码写错的规则不起作用
+    fn apply(&self, lines: &mut Vec<String>, body: &mut Option<Vec<u8>>, decoded: &[u8]) {
+        if lines.is_empty() { return; }
+        let name = self.name.trim();
+        let is_name = |line: &String| line.split(":").next().unwrap_or("").trim().eq_ignore_ascii_case(name);
+        match self.action {
+            RewriteAction::AddHeader if !name.is_empty() => lines.push(format!("{}: {}", name, self.value.trim())),
+            //同名的字段只留下一个，位置不变
+            RewriteAction::SetHeader if !name.is_empty() => {
+                let first = lines.remove(0);
+                let pos = lines.iter().position(is_name).unwrap_or(lines.len());
+                lines.retain(|line| !is_name(line));
+                lines.insert(pos, format!("{}: {}", name, self.value.trim()));
+                lines.insert(0, first);
+            }
+            RewriteAction::RemoveHeader if !name.is_empty() => {
+                let first = lines.remove(0);
+                lines.retain(|line| !is_name(line));
+                lines.insert(0, first);
+            }
+            RewriteAction::ReplaceBody if !name.is_empty() => {
+                let Ok(regex) = Regex::new(&self.name) else { return; };
+                let current = body.as_deref().unwrap_or(decoded);
+                let replaced = regex.replace_all(current, self.value.as_bytes());
+                if replaced.as_ref() != current { *body = Some(replaced.into_owned()); }
+            }
+            RewriteAction::SetStatus if self.phase == Phase::Response => {
+                let value = self.value.trim();
+                let (code, reason) = value.split_once(" ").unwrap_or((value, ""));
+                let Ok(code) = code.parse::<u16>() else { return; };
+                let reason = match reason.trim() {
+                    "" => HttpStatus::from_code(code, "").reason().to_string(),
+                    reason => reason.to_string(),
+                };
+                let version = lines[0].split(" ").next().unwrap_or("HTTP/1.1").to_string();
+                lines[0] = format!("{} {} {}", version, code, reason);
+            }
+            _ => {}
+        }
+    }
+}
+
+//按顺序应用改写规则，head是起始行和头字段（用\n分隔），body是去掉chunked但没有解压的内容，decoded是解压后的内容
+//返回改写后的头和body，以及真正改动了报文的规则说明，没有改动时返回None
+pub fn apply_rewrites(rewrites: &[Rewrite], head: &str, body: &[u8], decoded: &[u8]) -> Option<(String, Vec<u8>, Vec<String>)> {
+    let mut lines = head.lines().map(|line| line.to_string()).collect::<Vec<_>>();
+    let mut new_body = None;
+    let mut notes = vec![];
+    for rewrite in rewrites {
+        let (old_lines, old_body) = (lines.clone(), new_body.clone());
+        rewrite.apply(&mut lines, &mut new_body, decoded);
+        if lines != old_lines || new_body != old_body { notes.push(rewrite.describe()); }
+    }
+    if notes.is_empty() { return None; }
+    let body = match new_body {
+        //替换的是解压后的内容，压缩方式的字段要去掉
+        Some(body) => {
+            lines.retain(|line| !line.split(":").next().unwrap_or("").trim().eq_ignore_ascii_case("Content-Encoding"));
+            body
+        }
+        None => body.to_vec(),
+    };
+    Some((lines.join("\n"), body, notes))
+}
+
+
+#[cfg(test)]
+mod test_rewrite {
+    use crate::rule::rewrite::{apply_rewrites, Rewrite, RewriteAction};
+    use crate::rule::Phase;
+
+    fn rewrite(action: RewriteAction, name: &str, value: &str) -> Rewrite {
+        Rewrite { phase: Phase::Response, action, name: name.to_string(), value: value.to_string(), ..Rewrite::new() }
+    }
+
+    #[test]
+    fn test_apply_rewrites() {
+        let head = "HTTP/1.1 200 OK\nContent-Encoding: gzip\nX-A: 1\nSet-Cookie: a=1\nx-a: 2";
+        let rewrites = [
+            rewrite(RewriteAction::SetHeader, "X-A", "3"),
+            rewrite(RewriteAction::RemoveHeader, "Set-Cookie", ""),
+            rewrite(RewriteAction::AddHeader, "X-B", "4"),
+            rewrite(RewriteAction::ReplaceBody, r#""debug":\s*(\w+)"#, r#""debug": true"#),
+            rewrite(RewriteAction::SetStatus, "", "503"),
+            rewrite(RewriteAction::ReplaceBody, "not found", ""),
+        ];
+        let (head, body, notes) = apply_rewrites(&rewrites, head, b"gzipped", br#"{"debug": false}"#).unwrap();
+        assert_eq!(head, "HTTP/1.1 503 Service Unavailable\nX-A: 3\nX-B: 4");
+        assert_eq!(body, br#"{"debug": true}"#);
+        assert_eq!(notes.len(), 5);
+        assert!(apply_rewrites(&rewrites[5..], "HTTP/1.1 200 OK", b"", b"").is_none());
+    }
+}
@@ -0,0 +1,54 @@
+use crate::rule::host_matches;
+
+//HTTPS是否解密，按CONNECT的主机匹配，不解密的连接原样转发
+#[derive(Clone, PartialEq)]
+pub struct SslRule {
+    pub enabled: bool,
+    pub host: String,
+    pub decrypt: bool,
+    //客户端多次握手失败后自动添加的
+    pub auto: bool,
+}
+
+impl SslRule {
+    pub fn new() -> SslRule {
+        SslRule { enabled: true, host: String::new(), decrypt: false, auto: false }
+    }
+
+    //自动添加的规则只对这一个地址生效
+    pub fn auto(authority: &str) -> SslRule {
+        SslRule { enabled: true, host: authority.to_string(), decrypt: false, auto: true }
+    }
+
+    pub fn matches(&self, authority: &str) -> bool {
+        host_matches(&self.host, authority)
+    }
+}
+
+
+#[cfg(test)]
+mod test_ssl {
+    use crate::rule::ssl::SslRule;
+    use crate::rule::Rules;
+
+    #[test]
+    fn test_ssl_rule() {
+        let mut rules = Rules::default();
+        assert!(rules.decrypt("api.example.com:443"));
+        let mut rule = SslRule::new();
+        rule.host = "*.bank.com".to_string();
+        rules.ssl_rules.push(rule);
+        assert!(!rules.decrypt("www.bank.com:443"));
+        assert!(rules.decrypt("api.example.com:443"));
+
+        //连续失败到一定次数才改为不解密，中间成功一次就重新计数
+        assert!(!rules.handshake_failed("api.example.com:443"));
+        rules.handshake_succeeded("api.example.com:443");
+        assert!(!rules.handshake_failed("api.example.com:443"));
+        assert!(!rules.handshake_failed("api.example.com:443"));
+        assert!(rules.handshake_failed("api.example.com:443"));
+        assert!(!rules.decrypt("api.example.com:443"));
+        assert!(rules.decrypt("www.example.com:443"));
+        assert!(rules.ssl_rules[0].auto);
+    }
+}
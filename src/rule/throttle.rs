@@ -0,0 +1,173 @@
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+use crate::data::StreamDirection;
+use crate::rule::host_matches;
+
+//常用的网络环境，选中之后把参数填到规则里，之后还可以再改
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    Custom,
+    Slow3G,
+    Fast3G,
+    Slow4G,
+    Fast4G,
+}
+
+impl Profile {
+    pub fn profiles() -> [Profile; 5] {
+        [Profile::Custom, Profile::Slow3G, Profile::Fast3G, Profile::Slow4G, Profile::Fast4G]
+    }
+
+    //延迟（毫秒）、下行和上行带宽（kbps）
+    pub fn settings(&self) -> Option<(u32, u32, u32)> {
+        match self {
+            Profile::Custom => None,
+            Profile::Slow3G => Some((400, 400, 400)),
+            Profile::Fast3G => Some((150, 1600, 750)),
+            Profile::Slow4G => Some((100, 4000, 1500)),
+            Profile::Fast4G => Some((40, 12000, 5000)),
+        }
+    }
+}
+
+impl Display for Profile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Profile::Custom => f.write_str("自定义"),
+            Profile::Slow3G => f.write_str("慢速3G"),
+            Profile::Fast3G => f.write_str("3G"),
+            Profile::Slow4G => f.write_str("慢速4G"),
+            Profile::Fast4G => f.write_str("4G"),
+        }
+    }
+}
+
+//模拟的网络故障
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Fault {
+    //收到响应后不发给客户端，直接重置连接
+    Reset,
+    //响应一直不发给客户端，直到客户端自己断开
+    Stall,
+    //响应头完整发出，body只发一半就断开连接
+    Truncate,
+}
+
+impl Display for Fault {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Fault::Reset => f.write_str("连接断开"),
+            Fault::Stall => f.write_str("响应卡住"),
+            Fault::Truncate => f.write_str("响应截断"),
+        }
+    }
+}
+
+//网络环境模拟，主机为空时对所有请求生效，带宽为0表示不限速，故障按百分比随机发生
+#[derive(Clone, PartialEq)]
+pub struct Throttle {
+    pub enabled: bool,
+    pub host: String,
+    pub profile: Profile,
+    pub latency: u32,
+    pub download: u32,
+    pub upload: u32,
+    pub reset: u32,
+    pub stall: u32,
+    pub truncate: u32,
+}
+
+impl Throttle {
+    pub fn new() -> Throttle {
+        let mut throttle = Throttle {
+            enabled: true,
+            host: String::new(),
+            profile: Profile::Fast3G,
+            latency: 0,
+            download: 0,
+            upload: 0,
+            reset: 0,
+            stall: 0,
+            truncate: 0,
+        };
+        throttle.apply_profile();
+        throttle
+    }
+
+    pub fn apply_profile(&mut self) {
+        if let Some((latency, download, upload)) = self.profile.settings() {
+            (self.latency, self.download, self.upload) = (latency, download, upload);
+        }
+    }
+
+    pub fn matches(&self, authority: &str) -> bool {
+        host_matches(&self.host, authority)
+    }
+
+    pub fn shaper(&self, direction: StreamDirection) -> Shaper {
+        let kbps = match direction {
+            StreamDirection::ClientToServer => self.upload,
+            StreamDirection::ServerToClient => self.download,
+        };
+        Shaper { latency: Duration::from_millis(self.latency as u64), kbps }
+    }
+
+    //每个请求掷一次骰子，最多发生一种故障
+    pub fn roll_fault(&self) -> Option<Fault> {
+        let dice = fastrand::u32(0..100);
+        [(Fault::Reset, self.reset), (Fault::Stall, self.stall), (Fault::Truncate, self.truncate)].into_iter()
+            .scan(0, |sum, (fault, percent)| {
+                *sum += percent.min(100);
+                Some((fault, *sum))
+            })
+            .find(|(_, sum)| dice < *sum).map(|(fault, _)| fault)
+    }
+}
+
+//一个方向上的延迟和带宽，默认不做限制
+#[derive(Clone, Default)]
+pub struct Shaper {
+    latency: Duration,
+    kbps: u32,
+}
+
+impl Shaper {
+    pub fn latency(&self) -> Duration {
+        self.latency
+    }
+
+    //按带宽算出传输这么多数据需要的时间
+    pub fn transfer_time(&self, len: usize) -> Duration {
+        if self.kbps == 0 { return Duration::ZERO; }
+        Duration::from_secs_f64(len as f64 * 8.0 / (self.kbps as f64 * 1000.0))
+    }
+
+    pub async fn pace(&self, len: usize) {
+        let time = self.transfer_time(len);
+        if !time.is_zero() { tokio::time::sleep(time).await; }
+    }
+}
+
+
+#[cfg(test)]
+mod test_throttle {
+    use std::time::Duration;
+    use crate::data::StreamDirection;
+    use crate::rule::throttle::{Fault, Profile, Throttle};
+
+    #[test]
+    fn test_throttle() {
+        let mut throttle = Throttle::new();
+        throttle.host = "*.example.com".to_string();
+        throttle.profile = Profile::Slow3G;
+        throttle.apply_profile();
+        assert!(throttle.matches("api.example.com:443"));
+        assert!(!throttle.matches("example.org"));
+        let shaper = throttle.shaper(StreamDirection::ServerToClient);
+        assert_eq!(shaper.latency(), Duration::from_millis(400));
+        assert_eq!(shaper.transfer_time(50_000), Duration::from_secs(1));
+        assert_eq!(throttle.roll_fault(), None);
+        throttle.truncate = 100;
+        assert_eq!(throttle.roll_fault(), Some(Fault::Truncate));
+    }
+}
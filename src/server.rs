@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+use log::{debug, error, info};
+use tokio::net::TcpListener;
+use tokio::runtime::Runtime;
+use tokio::sync;
+use crate::cert;
+use crate::data::http::HttpPacket;
+use crate::data::{HttpTcpData, ProxyEvent, StreamInfo};
+use crate::error::ProxyResult;
+use crate::proxy::ProxyStream;
+use crate::rule::breakpoint::Paused;
+use crate::rule::SharedRules;
+
+//代理服务，由界面持有，启动时在界面的运行时中开启监听，停止时通知所有任务退出
+pub struct ProxyServer {
+    shutdown: sync::watch::Sender<bool>,
+    packets: sync::mpsc::Receiver<HttpPacket>,
+    //被断点拦住、等待界面处理的报文
+    pauses: sync::mpsc::Receiver<Paused>,
+}
+
+impl ProxyServer {
+    pub fn start(runtime: &Runtime, addr: &str, ctx: egui::Context, rules: SharedRules) -> ProxyResult<ProxyServer> {
+        //没有根证书时先生成一个，证书缓存目录也要提前建好
+        if !std::fs::exists("sca.pem")? || !std::fs::exists("sca.key")? {
+            cert::gen_ca()?;
+        }
+        std::fs::create_dir_all("target/tmp/certs")?;
+        //这里同步绑定端口，端口被占用时可以直接把错误返回给界面
+        let listen = runtime.block_on(TcpListener::bind(addr))?;
+        info!("在本地{}建立一个Tcp端口监听服务", addr);
+        let (shutdown, shutdown_rx) = sync::watch::channel(false);
+        let (sx, rx) = sync::mpsc::channel(1024);
+        let (packet_sx, packets) = sync::mpsc::channel(1024);
+        let (pause_sx, pause_rx) = sync::mpsc::channel(64);
+        let (paused_sx, pauses) = sync::mpsc::channel(64);
+        let repaint = ctx.clone();
+        runtime.spawn(async move {
+            receive_data(rx, packet_sx, ctx).await;
+        });
+        runtime.spawn(async move {
+            forward_pauses(pause_rx, paused_sx, repaint).await;
+        });
+        runtime.spawn(async move {
+            accept_loop(listen, sx, pause_sx, rules, shutdown_rx).await.unwrap_or_else(|e| error!("{}",e.to_string()));
+        });
+        Ok(ProxyServer { shutdown, packets, pauses })
+    }
+
+    //停止监听，并断开已建立的连接
+    pub fn stop(self) {
+        self.shutdown.send_replace(true);
+    }
+
+    //取出已经抓到的数据包，界面每帧调用一次
+    pub fn packets(&mut self) -> Vec<HttpPacket> {
+        let mut res = vec![];
+        while let Ok(packet) = self.packets.try_recv() {
+            res.push(packet);
+        }
+        res
+    }
+
+    //取出新被断点拦住的报文
+    pub fn pauses(&mut self) -> Vec<Paused> {
+        let mut res = vec![];
+        while let Ok(paused) = self.pauses.try_recv() {
+            res.push(paused);
+        }
+        res
+    }
+}
+
+async fn accept_loop(listen: TcpListener, sx: sync::mpsc::Sender<ProxyEvent>, pause_sx: sync::mpsc::Sender<Paused>,
+                     rules: SharedRules, mut shutdown: sync::watch::Receiver<bool>) -> ProxyResult<()> {
+    loop {
+        //接受一个新连接，收到停止信号时退出
+        let (stream, addr) = tokio::select! {
+            res = listen.accept() => res?,
+            _ = shutdown.changed() => break,
+        };
+        debug!("来自{}的新连接",addr);
+        //启动一个线程，避免造成其他连接阻塞，影响网络体验
+        let sender = sx.clone();
+        let pauses = pause_sx.clone();
+        let rules = rules.clone();
+        let mut shutdown = shutdown.clone();
+        tokio::spawn(async move {
+            tokio::select! {
+                res = ProxyStream::new(stream, sender, rules, pauses).start() => res.unwrap_or_else(|e| error!("{}",e.to_string())),
+                _ = shutdown.changed() => debug!("代理已停止，断开来自{}的连接",addr),
+            }
+        });
+    }
+    info!("已停止监听");
+    Ok(())
+}
+
+//按stream区分数据，每个连接单独拼装
+fn receive_once(event: ProxyEvent, data: &mut HashMap<String, HttpTcpData>) -> ProxyResult<Vec<HttpPacket>> {
+    let pd = match event {
+        ProxyEvent::Open(info) => {
+            data.insert(info.stream_id().to_string(), HttpTcpData::new(*info));
+            return Ok(vec![]);
+        }
+        ProxyEvent::Data(pd) => *pd,
+    };
+    let stream_id = pd.stream_id().to_string();
+    let tcp_data = data.entry(stream_id.clone())
+        .or_insert_with(|| HttpTcpData::new(StreamInfo::new(&stream_id, "http", "")));
+    //解析出错时，前面已经解析好的数据还是要交给界面
+    tcp_data.push(pd).unwrap_or_else(|e| error!("{}",e.to_string()));
+    let packets = tcp_data.packets();
+    //连接两个方向都断开了，清理掉这个连接的数据
+    if tcp_data.closed() { data.remove(&stream_id); }
+    Ok(packets)
+}
+
+async fn receive_data(mut rx: sync::mpsc::Receiver<ProxyEvent>, packet_sx: sync::mpsc::Sender<HttpPacket>, ctx: egui::Context) {
+    let mut data = HashMap::new();
+    //所有连接都断开且监听停止后，发送端全部释放，这里就会退出
+    while let Some(event) = rx.recv().await {
+        match receive_once(event, &mut data) {
+            Ok(packets) => {
+                if packets.is_empty() { continue; }
+                for packet in packets {
+                    if packet_sx.send(packet).await.is_err() { return; }
+                }
+                //有新数据时通知界面刷新
+                ctx.request_repaint();
+            }
+            Err(e) => error!("{}",e.to_string()),
+        }
+    }
+}
+
+//断点拦住报文时通知界面刷新，界面不刷新就看不到等待处理的报文
+async fn forward_pauses(mut rx: sync::mpsc::Receiver<Paused>, paused_sx: sync::mpsc::Sender<Paused>, ctx: egui::Context) {
+    while let Some(paused) = rx.recv().await {
+        if paused_sx.send(paused).await.is_err() { return; }
+        ctx.request_repaint();
+    }
+}